@@ -1,14 +1,82 @@
 // import the wasm module and the summarize function
 import * as wasm from './wasm/summy_background.js';
-import { MODEL_KEY, API_KEY_KEY, DEFAULT_MODEL } from './constants.js';
+import {
+    MODEL_KEY,
+    API_KEY_KEY,
+    ENDPOINTS_KEY,
+    DEFAULT_MODEL,
+    SEMANTIC_SEARCH_KEY,
+    SEMANTIC_SEARCH_DEFAULT,
+    SESSIONS_KEY,
+    SUMMARY_CACHE_KEY
+} from './constants.js';
 
-(async function() {
+// Chrome stops the service worker when it is idle, so we restore the sessions
+// and summaries saved before, wait for this before using the sessions
+const ready = (async function() {
     await wasm.default();
+
+    try {
+        const items = await chrome.storage.session.get([SESSIONS_KEY, SUMMARY_CACHE_KEY]);
+        if (items[SESSIONS_KEY]) {
+            const restored = wasm.restore_sessions(items[SESSIONS_KEY]);
+            const expired = wasm.sweep_expired();
+            console.log(`Restored ${restored} sessions, ${expired} expired`);
+        }
+        if (items[SUMMARY_CACHE_KEY]) {
+            const restored = wasm.restore_summary_cache(items[SUMMARY_CACHE_KEY]);
+            console.log(`Restored ${restored} cached summaries`);
+        }
+    } catch (error) {
+        console.error("Error restoring sessions:", error);
+    }
+
+    const items = await chrome.storage.sync.get({[ENDPOINTS_KEY]: ''});
+    configureEndpoints(items[ENDPOINTS_KEY]);
 })();
 
+// Send models with a custom endpoint to that endpoint
+function configureEndpoints(endpoints) {
+    try {
+        wasm.configure_endpoints(endpoints || '');
+    } catch (error) {
+        console.error("Error configuring endpoints:", error);
+    }
+}
+
+chrome.storage.onChanged.addListener(async (changes, area) => {
+    if (area === 'sync' && changes[ENDPOINTS_KEY]) {
+        await ready;
+        configureEndpoints(changes[ENDPOINTS_KEY].newValue);
+    }
+});
+
+// Maximum size of the saved sessions. The session storage holds 10 MB, the JSON
+// of the sessions is escaped once more when stored and shares it with the cache.
+const MAX_SAVED_SESSIONS_BYTES = 4 * 1024 * 1024;
+
+// Below this size we stop retrying to save fewer sessions
+const MIN_SAVED_SESSIONS_BYTES = 64 * 1024;
+
+// Save the sessions and cached summaries to survive restarts of the service worker.
+// Least recently used sessions are left out if they don't fit into the storage.
+function saveSessions(maxBytes = MAX_SAVED_SESSIONS_BYTES) {
+    wasm.sweep_expired();
+    return chrome.storage.session.set({
+        [SESSIONS_KEY]: wasm.save_sessions(maxBytes),
+        [SUMMARY_CACHE_KEY]: wasm.save_summary_cache()
+    }).catch(error => {
+        if (/quota/i.test(error.message) && maxBytes > MIN_SAVED_SESSIONS_BYTES) {
+            return saveSessions(Math.floor(maxBytes / 2));
+        }
+        console.error("Error saving sessions:", error);
+    });
+}
+
 console.log("Background script started");
 
 const CONTEXT_MENU_KEY = "summyContextMenu"
+const CONTEXT_MENU_REFRESH_KEY = "summyContextMenuRefresh"
 
 chrome.contextMenus.create({
     id: CONTEXT_MENU_KEY,
@@ -16,16 +84,32 @@ chrome.contextMenus.create({
     contexts:["page"],
 });
 
+// Summarize the whole page again, instead of what changed or the cached summary
+chrome.contextMenus.create({
+    id: CONTEXT_MENU_REFRESH_KEY,
+    title: "Summarize again from scratch",
+    contexts:["page"],
+});
+
 chrome.contextMenus.onClicked.addListener((info, tab) => {
-    if (info.menuItemId === CONTEXT_MENU_KEY) {
+    if (info.menuItemId === CONTEXT_MENU_KEY || info.menuItemId === CONTEXT_MENU_REFRESH_KEY) {
+        const forceRefresh = info.menuItemId === CONTEXT_MENU_REFRESH_KEY;
         // Execute a content script to get the page HTML
         chrome.scripting.executeScript({
             target: { tabId: tab.id },
-            function: () => document.documentElement.outerHTML
+            function: () => ({
+                contentType: document.contentType,
+                // PDFs are fetched by the background script
+                content: document.contentType === "text/html"
+                    ? document.documentElement.outerHTML
+                    : document.contentType === "application/pdf"
+                        ? ""
+                        : document.body.innerText
+            })
         }, (results) => {
             if (results && results[0] && results[0].result) {
-                const html = results[0].result;
-                summarizePage(tab, html);
+                const { content, contentType } = results[0].result;
+                summarizePage(tab, content, contentType, forceRefresh);
             } else {
                 console.error("Failed to get page HTML");
             }
@@ -33,9 +117,15 @@ chrome.contextMenus.onClicked.addListener((info, tab) => {
     }
 });
 
-function summarizePage(tab, html) {
+function summarizePage(tab, content, contentType, forceRefresh = false) {
     // Get the model and API key from storage
-    chrome.storage.sync.get({[MODEL_KEY]: DEFAULT_MODEL, [API_KEY_KEY]: ''}, function(items) {
+    chrome.storage.sync.get({
+        [MODEL_KEY]: DEFAULT_MODEL,
+        [API_KEY_KEY]: '',
+        [SEMANTIC_SEARCH_KEY]: SEMANTIC_SEARCH_DEFAULT
+    }, async function(items) {
+        await ready;
+
         const model = items[MODEL_KEY];
         const apiKey = items[API_KEY_KEY];
 
@@ -44,16 +134,42 @@ function summarizePage(tab, html) {
             return;
         }
 
-        return wasm.summarize(getSessionId(tab), html, model, apiKey).then(function (summary) {
+        return summarizeContent(tab, content, contentType, model, apiKey, forceRefresh).then(function (summary) {
             console.log("summarize success:\n", summary);
             displaySummary(tab, summary, null);
+            saveSessions();
+
+            if (items[SEMANTIC_SEARCH_KEY]) {
+                embedDocument(tab, model, apiKey);
+            }
         }).catch(function (error) {
-            console.log("summarize error:", error);
-            displaySummary(tab, null, "Failed to summarize webpage");
+            console.log("summarize error:", error.code, error.detail || error);
+            displaySummary(tab, null, errorMessage(error, "Failed to summarize webpage"));
         });
     });
 };
 
+// PDFs are fetched and summarized from their bytes, pages summarized
+// before get a summary of what changed since then
+async function summarizeContent(tab, content, contentType, model, apiKey, forceRefresh) {
+    if (contentType === "application/pdf") {
+        const response = await fetch(tab.url);
+        if (!response.ok) {
+            throw new Error(`Failed to fetch PDF: ${response.status}`);
+        }
+        const bytes = new Uint8Array(await response.arrayBuffer());
+        return wasm.summarize_pdf(getSessionId(tab), bytes, model, apiKey, forceRefresh);
+    }
+
+    return wasm.summarize_changes(getSessionId(tab), tab.url, content, contentType || "", model, apiKey, forceRefresh);
+}
+
+// Errors of the wasm module carry a message for users, a stable code and whether
+// trying again may help. Other errors get the given fallback message.
+function errorMessage(error, fallback) {
+    return error && error.name === "SummyError" ? error.message : fallback;
+}
+
 function displaySummary(tab, summary, error) {
     try {
         chrome.tabs.sendMessage(tab.id,
@@ -67,11 +183,24 @@ function displaySummary(tab, summary, error) {
         console.debug(`Error sending message to tab ${tab.id}:`, error);
     }
 }
+// Embed the page in the background, follow-up questions use keyword search until
+// the embeddings are ready. Embeddings aren't saved with the sessions, restored
+// sessions get them again with their next follow-up question.
+function embedDocument(tab, model, apiKey) {
+    wasm.embed_document(getSessionId(tab), model, apiKey)
+        .catch(error => console.log("embedding error:", error));
+}
+
 // User has a follow-up question or comment
 function followUp(tab, question) {
     // Get API key and model from storage
-    return chrome.storage.sync.get({[MODEL_KEY]: DEFAULT_MODEL, [API_KEY_KEY]: ''})
-        .then(items => {
+    const defaults = {
+        [MODEL_KEY]: DEFAULT_MODEL,
+        [API_KEY_KEY]: '',
+        [SEMANTIC_SEARCH_KEY]: SEMANTIC_SEARCH_DEFAULT
+    };
+    return chrome.storage.sync.get(defaults)
+        .then(async items => {
             const model = items[MODEL_KEY];
             const apiKey = items[API_KEY_KEY];
 
@@ -79,7 +208,13 @@ function followUp(tab, question) {
                 throw new Error("API key is not set. Please set it in the extension options.");
             }
 
-            return wasm.follow_up(getSessionId(tab), question, model, apiKey);
+            await ready;
+            if (items[SEMANTIC_SEARCH_KEY]) {
+                embedDocument(tab, model, apiKey);
+            }
+            const answer = await wasm.follow_up(getSessionId(tab), question, model, apiKey);
+            saveSessions();
+            return answer;
         });
 }
 
@@ -89,7 +224,7 @@ chrome.runtime.onMessage.addListener((request, _, sendResponse) => {
             try {
                 getCurrentTab(tab => {
                     if (tab) {
-                        summarizePage(tab, request.html);
+                        summarizePage(tab, request.html, request.contentType);
                     }
                 });
 
@@ -109,17 +244,22 @@ chrome.runtime.onMessage.addListener((request, _, sendResponse) => {
                     });
                 }).catch(error => {
                     console.error("Error processing follow-up question:", error);
+                    // Error objects don't survive messaging, send their properties
                     sendResponse({
                         success: false,
-                        error: error
+                        error: errorMessage(error, error.message),
+                        code: error.code,
+                        retryable: error.retryable
                     });
                 });
             });
             break;
         case "summy_cleanup":
             try {
-                getCurrentTab(tab => {
+                getCurrentTab(async tab => {
+                    await ready;
                     wasm.cleanup(getSessionId(tab));
+                    saveSessions();
                 });
                 sendResponse({success: true});
             } catch (error) {
@@ -1,6 +1,11 @@
 // Storage keys for Summy extension
 export const MODEL_KEY = 'llm_model';
 export const API_KEY_KEY = 'llm_api_key';
+export const ENDPOINTS_KEY = 'llm_endpoints';
 export const SHOW_BUTTON_KEY = 'show_button';
 export const SHOW_BUTTON_DEFAULT = true;
+export const SEMANTIC_SEARCH_KEY = 'semantic_search';
+export const SEMANTIC_SEARCH_DEFAULT = false;
+export const SESSIONS_KEY = 'sessions';
+export const SUMMARY_CACHE_KEY = 'summary_cache';
 export const DEFAULT_MODEL = ''; // no default model for now
\ No newline at end of file
@@ -48,7 +48,18 @@ function createSummyView(css) {
                 let data = JSON.parse(request.result);
 
                 div.appendChild(createStressScore(data.stress_score, data.emoji_outline));
-                div.appendChild(createContent(data.category, data.summary, data.emoji_outline));
+
+                let content = createContent(data.category, data.summary, data.emoji_outline);
+
+                // the summary only covers what changed since the last visit
+                if (data.previous_version && data.changes && data.changes.added + data.changes.modified > 0) {
+                    let changes = document.createElement("div");
+                    changes.classList.add("content-changes");
+                    changes.textContent = "What changed since you last read this on "
+                        + new Date(data.previous_version).toLocaleString();
+                    content.querySelector(".content-title").after(changes);
+                }
+                div.appendChild(content);
                 div.appendChild(createQuestions(data.questions, data.answers, div));
             }
 
@@ -114,10 +125,16 @@ function createSummyButton(css) {
     button.appendChild(icon);
     button.classList.add("not-loading");
     button.onclick = function () {
-        const domString = document.documentElement.outerHTML;
+        const contentType = document.contentType;
+
+        // Browsers wrap non-HTML documents like plain text, markdown
+        // or JSON in a generated page, send the raw text for those
+        const content = contentType === "text/html" || contentType === "application/xhtml+xml"
+            ? document.documentElement.outerHTML
+            : document.body.innerText;
 
         chrome.runtime.sendMessage(
-            {msg: "summy_summarize", html: domString},
+            {msg: "summy_summarize", html: content, contentType: contentType},
             function () {
                 if (chrome.runtime.lastError) {
                     console.log("Summy summarize error:", chrome.runtime.lastError.message);
@@ -2,9 +2,12 @@ import * as wasm from './wasm/summy_background.js';
 import {
   MODEL_KEY,
   API_KEY_KEY,
+  ENDPOINTS_KEY,
   DEFAULT_MODEL,
   SHOW_BUTTON_KEY,
-  SHOW_BUTTON_DEFAULT
+  SHOW_BUTTON_DEFAULT,
+  SEMANTIC_SEARCH_KEY,
+  SEMANTIC_SEARCH_DEFAULT
  } from './constants.js';
 
 // Get the version from manifest.json
@@ -23,24 +26,32 @@ async function loadOptions() {
   const result = await chrome.storage.sync.get({
     [MODEL_KEY]: DEFAULT_MODEL,
     [API_KEY_KEY]: '',
-    [SHOW_BUTTON_KEY]: SHOW_BUTTON_DEFAULT
+    [ENDPOINTS_KEY]: '',
+    [SHOW_BUTTON_KEY]: SHOW_BUTTON_DEFAULT,
+    [SEMANTIC_SEARCH_KEY]: SEMANTIC_SEARCH_DEFAULT
   });
 
   document.getElementById('model').value = result[MODEL_KEY];
   document.getElementById('api-key').value = result[API_KEY_KEY];
+  document.getElementById('endpoints').value = result[ENDPOINTS_KEY];
   document.getElementById('show-button').checked = result[SHOW_BUTTON_KEY];
+  document.getElementById('semantic-search').checked = result[SEMANTIC_SEARCH_KEY];
 }
 
 // Save options
 async function saveOptions() {
   const model = document.getElementById('model').value;
   const apiKey = document.getElementById('api-key').value;
+  const endpoints = document.getElementById('endpoints').value;
   const showButton = document.getElementById('show-button').checked;
+  const semanticSearch = document.getElementById('semantic-search').checked;
 
   await chrome.storage.sync.set({
     [MODEL_KEY]: model,
     [API_KEY_KEY]: apiKey,
-    [SHOW_BUTTON_KEY]: showButton
+    [ENDPOINTS_KEY]: endpoints,
+    [SHOW_BUTTON_KEY]: showButton,
+    [SEMANTIC_SEARCH_KEY]: semanticSearch
   });
 
   // Notify all tabs about the button visibility change
@@ -54,6 +65,35 @@ async function saveOptions() {
   }
 }
 
+// Suggest the models of the providers with keys of their own, the models Summy
+// knows of for the other providers, and the models with a custom endpoint
+async function loadModels() {
+  const apiKey = document.getElementById('api-key').value;
+  const endpoints = document.getElementById('endpoints').value;
+  const modelList = document.getElementById('model-list');
+
+  try {
+    wasm.configure_endpoints(endpoints);
+    const providers = JSON.parse(await wasm.list_models('', apiKey));
+    modelList.replaceChildren();
+    for (const [provider, { models }] of Object.entries(providers)) {
+      for (const model of models) {
+        const hints = [provider, `${Math.round(model.context_size / 1000)}k`];
+        if (model.vision) {
+          hints.push('vision');
+        }
+
+        const option = document.createElement('option');
+        option.value = model.name;
+        option.label = `${model.name} (${hints.join(', ')})`;
+        modelList.appendChild(option);
+      }
+    }
+  } catch (error) {
+    console.error("Error listing models:", error);
+  }
+}
+
 // Test LLM connection
 async function testLLM() {
   const responseElement = document.getElementById('test-response');
@@ -66,20 +106,47 @@ async function testLLM() {
     // Get the current values
     const model = document.getElementById('model').value;
     const apiKey = document.getElementById('api-key').value;
-
-    const result = await wasm.verify_access(model, apiKey);
-    responseElement.textContent = result;
+    const endpoints = document.getElementById('endpoints').value;
+
+    // Models with a custom endpoint are verified at that endpoint
+    wasm.configure_endpoints(endpoints);
+    const report = JSON.parse(await wasm.verify_access(model, apiKey));
+    responseElement.textContent = report.confirmed
+      ? `Access confirmed: ${describeTarget(report)}`
+      : `The model answered, but not as asked: "${report.reply}". ${describeTarget(report)}`;
   } catch (error) {
-    responseElement.textContent = `Error: ${error.message || error}`;
+    responseElement.textContent = `Error: ${describeFailure(error)}`;
   }
 }
 
+// Where the model was reached and how long it took, e.g. "OpenAI at https://api.openai.com/v1/ in 532 ms"
+function describeTarget(report) {
+  const latency = report.latency_ms !== null ? ` in ${report.latency_ms} ms` : '';
+  return `${report.adapter} at ${report.endpoint}${latency}`;
+}
+
+// verify_access fails with a SummyError that has the fields found out so far,
+// other errors are shown as they are
+function describeFailure(error) {
+  if (error?.name !== 'SummyError') {
+    return error?.message || error;
+  }
+
+  const target = error.adapter ? ` (${describeTarget(error)})` : '';
+  return `${error.message}${target}\n${error.code}: ${error.detail}`;
+}
+
 await loadOptions();
+loadModels();
 
 // Set up event listeners
 document.getElementById('model').addEventListener('change', saveOptions);
 document.getElementById('api-key').addEventListener('change', saveOptions);
+document.getElementById('api-key').addEventListener('change', loadModels);
+document.getElementById('endpoints').addEventListener('change', saveOptions);
+document.getElementById('endpoints').addEventListener('change', loadModels);
 document.getElementById('show-button').addEventListener('change', saveOptions);
+document.getElementById('semantic-search').addEventListener('change', saveOptions);
 document.getElementById('test-button').addEventListener('click', testLLM);
 
 // Password visibility toggle
@@ -0,0 +1,241 @@
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::{LazyLock, Mutex};
+
+// Maximum number of cached summaries. Summaries are small,
+// but there is no point in remembering every page ever read.
+const MAX_CACHED_SUMMARIES: usize = 200;
+
+// Maximum number of pages we remember the last summarized text of.
+// Snapshots hold the full text, so we keep fewer of them.
+const MAX_SNAPSHOTS: usize = 50;
+
+// Version of the JSON format of a saved cache, bump on incompatible changes
+const CACHE_FORMAT_VERSION: u64 = 1;
+
+// Global cache of the summaries we got from the LLM
+pub static CACHE: LazyLock<SummaryCache> = LazyLock::new(SummaryCache::new);
+
+// Summaries by text, model and options, so summarizing an unchanged
+// page again doesn't need to detect the language and summarize again
+pub struct SummaryCache {
+    summaries: Mutex<LruCache<String, CachedSummary>>,
+    stats: Mutex<CacheStats>,
+
+    // Text of pages by URL when they were last summarized,
+    // to tell what changed when they are summarized again
+    snapshots: Mutex<LruCache<String, Snapshot>>,
+}
+
+impl SummaryCache {
+    // Create a new cache. This function is private to ensure that the cache is only created once.
+    fn new() -> Self {
+        Self {
+            summaries: Mutex::new(LruCache::new(
+                NonZeroUsize::new(MAX_CACHED_SUMMARIES).unwrap(),
+            )),
+            stats: Mutex::new(CacheStats::default()),
+            snapshots: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_SNAPSHOTS).unwrap())),
+        }
+    }
+
+    // Get the cached summary for the given key, see `key`
+    pub fn get(&self, key: &str) -> Option<CachedSummary> {
+        let summary = self.summaries.lock().unwrap().get(key).cloned();
+
+        let mut stats = self.stats.lock().unwrap();
+        match summary {
+            Some(_) => stats.hits += 1,
+            None => stats.misses += 1,
+        }
+
+        summary
+    }
+
+    // Cache a summary, replacing the summary cached for the same key
+    pub fn put(&self, key: &str, summary: CachedSummary) {
+        self.summaries.lock().unwrap().put(key.to_string(), summary);
+    }
+
+    // Get the text of the page at the given URL when it was last summarized
+    pub fn snapshot(&self, url: &str) -> Option<Snapshot> {
+        self.snapshots.lock().unwrap().get(&page_key(url)).cloned()
+    }
+
+    // Remember the text of the page at the given URL as summarized now
+    pub fn set_snapshot(&self, url: &str, text: &str) {
+        let snapshot = Snapshot {
+            text: text.to_string(),
+            taken_at: crate::util::now(),
+        };
+        self.snapshots.lock().unwrap().put(page_key(url), snapshot);
+    }
+
+    // Number of cached summaries, hits and misses since the cache was created
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.summaries.lock().unwrap().len(),
+            ..*self.stats.lock().unwrap()
+        }
+    }
+
+    // Serialize all cached summaries and snapshots, from least to most recently used
+    pub fn to_json(&self) -> serde_json::Value {
+        let guard = self.summaries.lock().unwrap();
+        let summaries = guard
+            .iter()
+            .rev()
+            .map(|(key, summary)| {
+                serde_json::json!({
+                    "key": key,
+                    "language": summary.language,
+                    "summary": summary.summary,
+                })
+            })
+            .collect::<Vec<serde_json::Value>>();
+
+        let guard = self.snapshots.lock().unwrap();
+        let snapshots = guard
+            .iter()
+            .rev()
+            .map(|(url, snapshot)| {
+                serde_json::json!({
+                    "url": url,
+                    "text": snapshot.text,
+                    "taken_at": snapshot.taken_at,
+                })
+            })
+            .collect::<Vec<serde_json::Value>>();
+
+        serde_json::json!({
+            "version": CACHE_FORMAT_VERSION,
+            "summaries": summaries,
+            "snapshots": snapshots,
+        })
+    }
+
+    // Restore summaries and snapshots serialized with `to_json`. Entries that are
+    // cached already are kept. Returns the number of restored summaries.
+    pub fn restore(&self, json: &str) -> Result<usize, anyhow::Error> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+
+        let version = value["version"].as_u64().unwrap_or_default();
+        if version != CACHE_FORMAT_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported cache format version {}",
+                version
+            ));
+        }
+
+        let summaries = value["summaries"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("Missing summaries"))?
+            .iter()
+            .map(|summary| {
+                let field = |name: &str| string_field(summary, name);
+                let cached = CachedSummary {
+                    language: field("language")?,
+                    summary: field("summary")?,
+                };
+                Ok((field("key")?, cached))
+            })
+            .collect::<Result<Vec<(String, CachedSummary)>, anyhow::Error>>()?;
+
+        let snapshots = value["snapshots"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .map(|snapshot| {
+                let cached = Snapshot {
+                    text: string_field(snapshot, "text")?,
+                    taken_at: snapshot["taken_at"].as_u64().unwrap_or_default(),
+                };
+                Ok((string_field(snapshot, "url")?, cached))
+            })
+            .collect::<Result<Vec<(String, Snapshot)>, anyhow::Error>>()?;
+
+        insert_missing(&mut self.snapshots.lock().unwrap(), snapshots);
+        Ok(insert_missing(
+            &mut self.summaries.lock().unwrap(),
+            summaries,
+        ))
+    }
+}
+
+// Add the entries that aren't cached yet as the least recently
+// used ones, returns the number of added entries
+fn insert_missing<V>(cache: &mut LruCache<String, V>, entries: Vec<(String, V)>) -> usize {
+    let existing = cache
+        .iter()
+        .rev()
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<String>>();
+
+    let mut inserted = 0;
+    for (key, value) in entries {
+        if !cache.contains(&key) {
+            cache.push(key, value);
+            inserted += 1;
+        }
+    }
+
+    for key in existing {
+        cache.promote(&key);
+    }
+
+    inserted
+}
+
+fn string_field(value: &serde_json::Value, name: &str) -> Result<String, anyhow::Error> {
+    value[name]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Missing {} of cached entry", name))
+}
+
+// Snapshots are kept per page, the fragment only points into the page
+fn page_key(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut url) => {
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => url.to_string(),
+    }
+}
+
+// Cache key of the summary of the given text by the given model. The options
+// are everything else that changes the summary, like the prompts and schema.
+// The text is identified by the same hash as the documents of the sessions.
+pub fn key(text: &str, model: &str, options: &str) -> String {
+    format!(
+        "{}:{}:{}",
+        crate::util::content_hash(text),
+        crate::util::content_hash(options),
+        model
+    )
+}
+
+// Summary as returned to the caller, and the language it was written in
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CachedSummary {
+    pub language: String,
+    pub summary: String,
+}
+
+// Text of a page when it was last summarized
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub text: String,
+
+    // Milliseconds since the Unix epoch
+    pub taken_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
@@ -0,0 +1,256 @@
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Parser, Tag, TagEnd};
+
+// Tags that identify a document as HTML when sniffing the content type
+const HTML_MARKERS: [&str; 7] = [
+    "<!doctype",
+    "<html",
+    "<head",
+    "<body",
+    "<div",
+    "<p>",
+    "<article",
+];
+
+// Type of the content we are asked to summarize
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentType {
+    Html,
+    PlainText,
+    Markdown,
+    Json,
+}
+
+impl ContentType {
+    // Map a MIME type, e.g. the `document.contentType` of a page, to a content type.
+    // Parameters like `charset` are ignored.
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        let essence = mime.split(';').next().unwrap_or_default().trim();
+
+        match essence.to_lowercase().as_str() {
+            "text/html" | "application/xhtml+xml" => Some(Self::Html),
+            "text/plain" => Some(Self::PlainText),
+            "text/markdown" | "text/x-markdown" => Some(Self::Markdown),
+            "application/json" | "text/json" => Some(Self::Json),
+            other if other.ends_with("+json") => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    // Content type of content with the given MIME type. Content without a MIME type and
+    // text we don't know, e.g. "text/xml" or "application/rss+xml", is sniffed instead.
+    // Only binary content, e.g. "image/png", has no content type.
+    pub fn detect(mime: &str, content: &str) -> Option<Self> {
+        let essence = mime
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        if essence.is_empty() {
+            return Some(Self::sniff(content));
+        }
+
+        let is_text = essence.starts_with("text/")
+            || essence.ends_with("+xml")
+            || essence == "application/xml";
+        Self::from_mime(&essence).or_else(|| is_text.then(|| Self::sniff(content)))
+    }
+
+    // Guess the content type if we don't know the MIME type
+    pub fn sniff(content: &str) -> Self {
+        let trimmed = content.trim_start();
+
+        if (trimmed.starts_with('{') || trimmed.starts_with('['))
+            && serde_json::from_str::<serde_json::Value>(trimmed).is_ok()
+        {
+            return Self::Json;
+        }
+
+        let lower = trimmed
+            .chars()
+            .take(1024)
+            .collect::<String>()
+            .to_lowercase();
+        if HTML_MARKERS.iter().any(|tag| lower.contains(tag)) {
+            return Self::Html;
+        }
+
+        if looks_like_markdown(trimmed) {
+            return Self::Markdown;
+        }
+
+        Self::PlainText
+    }
+
+    // Additional instructions for the summarize prompt, if any
+    pub fn prompt(&self) -> Option<&'static str> {
+        match self {
+            Self::Json => Some(JSON_PROMPT),
+            Self::Markdown => Some(MARKDOWN_PROMPT),
+            _ => None,
+        }
+    }
+}
+
+// Extract the text to summarize from the given content
+pub fn extract(content: &str, content_type: ContentType) -> Result<String, anyhow::Error> {
+    match content_type {
+        ContentType::Html => crate::extract_text(content),
+        ContentType::PlainText => Ok(content.trim().to_string()),
+        ContentType::Markdown => Ok(markdown_to_text(content)),
+        ContentType::Json => {
+            let value: serde_json::Value = serde_json::from_str(content)?;
+            Ok(serde_json::to_string_pretty(&value)?)
+        }
+    }
+}
+
+// Title of the content, from the <title> element of HTML
+// or the first top-level heading of markdown
+pub fn title(content: &str, content_type: ContentType) -> Option<String> {
+    let title = match content_type {
+        ContentType::Html => html_title(content)?
+            .replace("&amp;", "&")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&#39;", "'"),
+        ContentType::Markdown => content
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("# "))?
+            .to_string(),
+        ContentType::PlainText | ContentType::Json => return None,
+    };
+
+    let title = title.split_whitespace().collect::<Vec<&str>>().join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
+// Content of the <title> element of an HTML document
+pub fn html_title(html: &str) -> Option<&str> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title>")?;
+    Some(&html[start..end])
+}
+
+fn looks_like_markdown(text: &str) -> bool {
+    let markers = text
+        .lines()
+        .take(200)
+        .map(str::trim_start)
+        .filter(|line| {
+            line.starts_with("# ")
+                || line.starts_with("## ")
+                || line.starts_with("### ")
+                || line.starts_with("```")
+                || line.starts_with("- ")
+                || line.starts_with("* ")
+                || line.starts_with("> ")
+                || (line.contains("](") && line.contains('['))
+        })
+        .count();
+
+    // a single bullet point doesn't make a markdown document
+    markers >= 2
+}
+
+// Render markdown as plain text while keeping its structure,
+// i.e. headings, lists, quotes and code blocks stay recognizable
+fn markdown_to_text(markdown: &str) -> String {
+    // block quotes are collected separately, to prefix their lines when they end
+    let mut buffers = vec![String::new()];
+    let mut list_depth = 0usize;
+
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::BlockQuote(_)) => {
+                buffers.push(String::new());
+                continue;
+            }
+            Event::End(TagEnd::BlockQuote(_)) if buffers.len() > 1 => {
+                let quote = buffers.pop().unwrap_or_default();
+                let out = buffers.last_mut().unwrap();
+                out.push_str("\n\n");
+                out.push_str(&quote_lines(quote.trim()));
+                continue;
+            }
+            _ => {}
+        }
+
+        let out = buffers.last_mut().unwrap();
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                out.push_str("\n\n");
+                out.push_str(&"#".repeat(heading_level(level)));
+                out.push(' ');
+            }
+            // paragraphs in list items stay on the line of the item
+            Event::Start(Tag::Paragraph) if list_depth == 0 => out.push_str("\n\n"),
+            Event::Start(Tag::List(_)) => {
+                list_depth += 1;
+                out.push('\n');
+            }
+            Event::End(TagEnd::List(_)) => {
+                list_depth = list_depth.saturating_sub(1);
+            }
+            Event::Start(Tag::Item) => {
+                out.push('\n');
+                out.push_str(&"  ".repeat(list_depth.saturating_sub(1)));
+                out.push_str("- ");
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                out.push_str("\n\n```");
+                if let CodeBlockKind::Fenced(lang) = kind {
+                    out.push_str(&lang);
+                }
+                out.push('\n');
+            }
+            Event::End(TagEnd::CodeBlock) => out.push_str("```"),
+            Event::Text(text) | Event::Code(text) => out.push_str(&text),
+            Event::SoftBreak => out.push(' '),
+            Event::HardBreak => out.push('\n'),
+            _ => {}
+        }
+    }
+
+    buffers.concat().trim().to_string()
+}
+
+// Mark every line of a block quote as quoted, keeping empty lines between paragraphs
+fn quote_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| match line.trim_end() {
+            "" => ">".to_string(),
+            line => format!("> {}", line),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn heading_level(level: HeadingLevel) -> usize {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+const JSON_PROMPT: &str = r#"
+    The text you are given is a JSON document, e.g. the response of a web API.
+    Do NOT describe it as code. Instead, explain what the data represents:
+    - Describe the overall purpose of the data
+    - Explain the meaning of the most important fields one by one, using their names
+    - Mention notable values, counts and ranges, keeping numbers and units precise
+"#;
+
+const MARKDOWN_PROMPT: &str = r#"
+    The text you are given is a Markdown document, e.g. a README.
+    Headings start with '#', list items with '-', code blocks are fenced with '```'.
+    Use the headings to understand the structure of the document, but
+    do not reproduce any of the markup in your response.
+"#;
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+// Header that selects the organization of a request to OpenAI
+const OPENAI_ORGANIZATION_HEADER: &str = "OpenAI-Organization";
+
+// Name under which the credentials for all other providers are given
+const DEFAULT_PROVIDER: &str = "default";
+
+// API keys and other credentials by provider, so users with keys
+// for several providers don't have to swap keys to switch models
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Credentials {
+    // Credentials by lowercase provider name, e.g. "openai" or "gemini"
+    providers: HashMap<String, Credential>,
+
+    // Credentials for providers that don't have their own
+    default: Option<Credential>,
+}
+
+// Credentials for a single provider
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Credential {
+    pub api_key: String,
+
+    // Additional headers sent with every request to the provider
+    pub headers: Vec<(String, String)>,
+}
+
+impl Credentials {
+    // Parse the credentials passed to the exports as `api_key`. That's either a single
+    // API key for all providers, or a JSON object with the credentials by provider, e.g.
+    //   {"openai": {"api_key": "sk-...", "organization": "org-..."}, "gemini": "AIza..."}
+    // A provider's credentials are a key, or an object with "api_key", "headers" and,
+    // for OpenAI, "organization". The "default" credentials are used for other providers.
+    pub fn parse(api_key: &str) -> Result<Self, anyhow::Error> {
+        let api_key = api_key.trim();
+        if !api_key.starts_with('{') {
+            return Ok(Self {
+                providers: HashMap::new(),
+                default: Some(Credential {
+                    api_key: api_key.to_string(),
+                    headers: Vec::new(),
+                }),
+            });
+        }
+
+        let value: serde_json::Value = serde_json::from_str(api_key)
+            .map_err(|e| anyhow::anyhow!("Invalid credentials: {}", e))?;
+
+        let mut credentials = Self::default();
+        for (provider, credential) in value.as_object().into_iter().flatten() {
+            let provider = provider.to_lowercase();
+
+            // a misspelled provider would silently fall back to the default key
+            if provider != DEFAULT_PROVIDER && crate::endpoints::adapter_kind(&provider).is_none() {
+                return Err(anyhow::anyhow!(
+                    "Unknown provider {} in credentials, expected one of {}, {}",
+                    provider,
+                    crate::endpoints::adapter_names().join(", "),
+                    DEFAULT_PROVIDER
+                ));
+            }
+
+            let credential = Credential::from_json(&provider, credential)?;
+            if provider == DEFAULT_PROVIDER {
+                credentials.default = Some(credential);
+            } else {
+                credentials.providers.insert(provider, credential);
+            }
+        }
+
+        Ok(credentials)
+    }
+
+    // Credentials for the provider with the given name, e.g. "openai"
+    pub fn get(&self, provider: &str) -> Option<&Credential> {
+        self.provider(provider).or(self.default.as_ref())
+    }
+
+    // Credentials given for the provider with the given name, without the default
+    pub fn provider(&self, provider: &str) -> Option<&Credential> {
+        self.providers.get(&provider.to_lowercase())
+    }
+}
+
+impl Credential {
+    fn from_json(provider: &str, value: &serde_json::Value) -> Result<Self, anyhow::Error> {
+        if let Some(api_key) = value.as_str() {
+            return Ok(Self {
+                api_key: api_key.to_string(),
+                headers: Vec::new(),
+            });
+        }
+
+        if !value.is_object() {
+            return Err(anyhow::anyhow!("Invalid credentials for {}", provider));
+        }
+
+        let mut headers = headers(value, provider)?;
+        if let Some(organization) = value["organization"].as_str() {
+            headers.push((
+                OPENAI_ORGANIZATION_HEADER.to_string(),
+                organization.to_string(),
+            ));
+        }
+
+        Ok(Self {
+            // some providers, like a local Ollama, don't need a key
+            api_key: value["api_key"].as_str().unwrap_or_default().to_string(),
+            headers,
+        })
+    }
+}
+
+// The "headers" of the given credentials or endpoint, all values need to be strings
+pub fn headers(
+    value: &serde_json::Value,
+    owner: &str,
+) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let mut headers = Vec::new();
+    for (name, header) in value["headers"].as_object().into_iter().flatten() {
+        let header = header
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid value of header {} for {}", name, owner))?;
+        headers.push((name.clone(), header.to_string()));
+    }
+
+    Ok(headers)
+}
@@ -0,0 +1,156 @@
+// Reply we ask for when verifying the access to a model
+pub const ACCESS_CONFIRMED: &str = "Access confirmed";
+
+// Why a request to the LLM failed, as far as we can tell from the error.
+// `SummyError` turns the cause into the code and message for the extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cause {
+    Cancelled,
+    Timeout,
+    ContextTooLong,
+    UnknownModel,
+    AuthRejected,
+    NetworkUnreachable,
+    QuotaExceeded,
+    MalformedResponse,
+}
+
+// Parts of the lowercase error text by cause, the first matching cause wins.
+// They are the fallback for errors without a telling status, providers report
+// their errors differently, these cover the wording of the providers we support.
+const CAUSE_MARKERS: [(Cause, &[&str]); 8] = [
+    (
+        Cause::Cancelled,
+        &["aborterror", "aborted", "cancelled", "canceled"],
+    ),
+    (
+        Cause::Timeout,
+        &[
+            "timed out",
+            "timeout",
+            "deadline exceeded",
+            "status: 408",
+            "status: 504",
+        ],
+    ),
+    (
+        Cause::ContextTooLong,
+        &[
+            "context_length_exceeded",
+            "context length",
+            "context window",
+            "maximum context",
+            "prompt is too long",
+            "too many tokens",
+            "request too large",
+            "status: 413",
+        ],
+    ),
+    (
+        Cause::AuthRejected,
+        &[
+            "status: 401",
+            "status: 403",
+            "unauthorized",
+            "invalid api key",
+            "invalid_api_key",
+            "incorrect api key",
+            "api key not valid",
+            "authentication",
+            "permission_denied",
+            "no api key",
+            "invalid credentials",
+        ],
+    ),
+    (
+        Cause::QuotaExceeded,
+        &[
+            "status: 429",
+            "quota",
+            "rate limit",
+            "rate_limit",
+            "resource_exhausted",
+            "insufficient_balance",
+            "credit balance",
+        ],
+    ),
+    (
+        Cause::UnknownModel,
+        &[
+            "status: 404",
+            "model_not_found",
+            "model not found",
+            "does not exist",
+            "not_found_error",
+            "is not found",
+            "unknown model",
+            "no model given",
+        ],
+    ),
+    (
+        Cause::NetworkUnreachable,
+        &[
+            "error sending request",
+            "failed to fetch",
+            "networkerror",
+            "connection",
+            "dns error",
+        ],
+    ),
+    (
+        Cause::MalformedResponse,
+        &[
+            "json",
+            "deserialize",
+            "decode",
+            "expected value",
+            "did not answer",
+        ],
+    ),
+];
+
+impl Cause {
+    // Tell the cause from an error of genai. Failed responses tell it by their
+    // status and requests that didn't go through are network failures, only the
+    // other errors fall back to the text of the error.
+    pub fn from_error(error: &genai::Error) -> Option<Self> {
+        let webc_error = match error {
+            genai::Error::WebModelCall { webc_error, .. }
+            | genai::Error::WebAdapterCall { webc_error, .. } => webc_error,
+            _ => return Self::classify(&format!("{:?}", error)),
+        };
+
+        match webc_error {
+            genai::webc::Error::ResponseFailedStatus { status, .. } => match status.as_u16() {
+                401 | 403 => Some(Cause::AuthRejected),
+                404 => Some(Cause::UnknownModel),
+                408 | 504 => Some(Cause::Timeout),
+                413 => Some(Cause::ContextTooLong),
+                429 => Some(Cause::QuotaExceeded),
+                _ => Self::classify(&format!("{:?}", error)),
+            },
+            genai::webc::Error::Reqwest(e) if e.is_timeout() => Some(Cause::Timeout),
+            genai::webc::Error::Reqwest(e) if e.is_decode() => Some(Cause::MalformedResponse),
+            genai::webc::Error::Reqwest(_) => Some(Cause::NetworkUnreachable),
+            _ => Self::classify(&format!("{:?}", error)),
+        }
+    }
+
+    // Tell the cause from the text of an error, if it's one we know
+    pub fn classify(error: &str) -> Option<Self> {
+        let error = error.to_lowercase();
+        CAUSE_MARKERS
+            .iter()
+            .find(|(_, markers)| markers.iter().any(|marker| error.contains(marker)))
+            .map(|(cause, _)| *cause)
+    }
+}
+
+// Whether the reply of the model is the confirmation we asked for,
+// models like to add punctuation, quotes or change the case
+pub fn is_confirmation(reply: &str) -> bool {
+    reply
+        .trim()
+        .trim_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace())
+        .eq_ignore_ascii_case(ACCESS_CONFIRMED)
+}
@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+
+// Share of terms two paragraphs need to have in common
+// to count as one modified paragraph instead of two changes
+const MODIFIED_SIMILARITY: f32 = 0.5;
+
+// Maximum number of cells of the table of common subsequences, about 4 MB.
+// Texts with more changed paragraphs than that are treated as replaced.
+const MAX_TABLE_CELLS: usize = 1_000_000;
+
+// What happened to a paragraph between two versions of a text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Removed,
+}
+
+impl ChangeKind {
+    pub const ALL: [ChangeKind; 3] = [ChangeKind::Added, ChangeKind::Modified, ChangeKind::Removed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Removed => "removed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub kind: ChangeKind,
+
+    // The paragraph in the new version, or the removed paragraph
+    pub text: String,
+
+    // The paragraph in the old version, for modified paragraphs
+    pub previous: Option<String>,
+}
+
+// Paragraphs that were added, modified or removed between two versions of a
+// text, in the order of the new version. Paragraphs are the non-empty lines
+// of the text, the same way the text is split for retrieval.
+pub fn diff(old: &str, new: &str) -> Vec<Change> {
+    let old = paragraphs(old);
+    let new = paragraphs(new);
+
+    // the unchanged beginning and end don't need to go through the table
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    // the table has a cell for every pair of paragraphs, so long texts
+    // that changed throughout are treated as replaced as a whole
+    if (old.len() + 1).saturating_mul(new.len() + 1) > MAX_TABLE_CELLS {
+        return replaced(old, new);
+    }
+
+    // lengths of the longest common subsequences of all suffixes
+    let mut lengths = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    // walk the table, collecting runs of removed and added
+    // paragraphs between paragraphs that didn't change
+    let mut changes = Vec::new();
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            changes.extend(pair(&mut removed, &mut added));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            added.push(new[j]);
+            j += 1;
+        } else {
+            removed.push(old[i]);
+            i += 1;
+        }
+    }
+    changes.extend(pair(&mut removed, &mut added));
+
+    changes
+}
+
+// All paragraphs of the new version added and those of the old one removed
+fn replaced(old: &[&str], new: &[&str]) -> Vec<Change> {
+    let change = |kind: ChangeKind, text: &&str| Change {
+        kind,
+        text: text.to_string(),
+        previous: None,
+    };
+
+    new.iter()
+        .map(|text| change(ChangeKind::Added, text))
+        .chain(old.iter().map(|text| change(ChangeKind::Removed, text)))
+        .collect()
+}
+
+// Turn a run of removed and added paragraphs into changes. An added paragraph
+// similar to a removed one is a modification of the removed paragraph.
+fn pair(removed: &mut Vec<&str>, added: &mut Vec<&str>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut unmatched = removed.drain(..).map(Some).collect::<Vec<Option<&str>>>();
+
+    for text in added.drain(..) {
+        let best = unmatched
+            .iter()
+            .enumerate()
+            .filter_map(|(i, previous)| Some((i, similarity(previous.as_ref()?, text))))
+            .filter(|(_, similarity)| *similarity >= MODIFIED_SIMILARITY)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        changes.push(match best {
+            Some((i, _)) => Change {
+                kind: ChangeKind::Modified,
+                text: text.to_string(),
+                previous: unmatched[i].take().map(str::to_string),
+            },
+            None => Change {
+                kind: ChangeKind::Added,
+                text: text.to_string(),
+                previous: None,
+            },
+        });
+    }
+
+    changes.extend(unmatched.into_iter().flatten().map(|text| Change {
+        kind: ChangeKind::Removed,
+        text: text.to_string(),
+        previous: None,
+    }));
+
+    changes
+}
+
+// Share of the distinct terms of two paragraphs that both have in common
+fn similarity(a: &str, b: &str) -> f32 {
+    let a = crate::retrieval::terms(a)
+        .into_iter()
+        .collect::<HashSet<String>>();
+    let b = crate::retrieval::terms(b)
+        .into_iter()
+        .collect::<HashSet<String>>();
+
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f32 / union as f32
+}
+
+fn paragraphs(text: &str) -> Vec<&str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
@@ -0,0 +1,262 @@
+use crate::credentials::Credential;
+use genai::adapter::AdapterKind;
+
+// Maximum number of texts we embed with a single request,
+// Gemini accepts at most 100 per batch
+const EMBEDDING_BATCH_SIZE: usize = 96;
+
+// Dimensions of the vectors produced by the fake embedder
+#[cfg(test)]
+const FAKE_DIMENSIONS: usize = 256;
+
+// Turns texts into vectors, similar texts get similar vectors
+pub trait Embedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, anyhow::Error>;
+}
+
+// Embed the chunks of a document in batches and index the vectors
+pub async fn index<E: Embedder>(
+    embedder: &E,
+    chunks: &[String],
+) -> Result<VectorIndex, anyhow::Error> {
+    let mut vectors = Vec::with_capacity(chunks.len());
+    for batch in chunks.chunks(EMBEDDING_BATCH_SIZE) {
+        let embedded = embedder.embed(batch).await?;
+        if embedded.len() != batch.len() {
+            return Err(anyhow::anyhow!(
+                "Expected {} embeddings, got {}",
+                batch.len(),
+                embedded.len()
+            ));
+        }
+        vectors.extend(embedded);
+    }
+
+    VectorIndex::new(vectors)
+}
+
+// Vectors of the chunks of a document. Vectors are normalized when they are added,
+// so the cosine similarity is just the dot product, and stored back to back in
+// a single buffer to keep the memory overhead per chunk low.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorIndex {
+    dimensions: usize,
+    vectors: Vec<f32>,
+}
+
+impl VectorIndex {
+    pub fn new(vectors: Vec<Vec<f32>>) -> Result<Self, anyhow::Error> {
+        let dimensions = vectors.first().map(Vec::len).unwrap_or_default();
+
+        let mut index = Self {
+            dimensions,
+            vectors: Vec::with_capacity(dimensions * vectors.len()),
+        };
+        for vector in vectors {
+            if vector.len() != dimensions {
+                return Err(anyhow::anyhow!(
+                    "Expected vector with {} dimensions, got {}",
+                    dimensions,
+                    vector.len()
+                ));
+            }
+            index.vectors.extend(normalize(vector));
+        }
+
+        Ok(index)
+    }
+
+    // Number of vectors in the index
+    pub fn len(&self) -> usize {
+        self.vectors
+            .len()
+            .checked_div(self.dimensions)
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    // Memory used by the vectors in bytes
+    pub fn bytes(&self) -> usize {
+        self.vectors.len() * std::mem::size_of::<f32>()
+    }
+
+    // Indices of the k vectors most similar to the query, in document order
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<usize> {
+        if query.len() != self.dimensions || self.is_empty() {
+            return Vec::new();
+        }
+
+        let query = normalize(query.to_vec());
+        let mut scores = self
+            .vectors
+            .chunks(self.dimensions)
+            .map(|vector| vector.iter().zip(&query).map(|(a, b)| a * b).sum::<f32>())
+            .enumerate()
+            .filter(|(_, score)| *score > 0.0)
+            .collect::<Vec<(usize, f32)>>();
+
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut top = scores
+            .into_iter()
+            .take(k)
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        top.sort();
+        top
+    }
+}
+
+// Scale a vector to unit length
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+// Deterministic embedder for tests. Terms are hashed into buckets, so
+// texts that share terms get similar vectors. No network required.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FakeEmbedder;
+
+#[cfg(test)]
+impl Embedder for FakeEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, anyhow::Error> {
+        Ok(texts
+            .iter()
+            .map(|text| {
+                let mut vector = vec![0.0; FAKE_DIMENSIONS];
+                for term in crate::retrieval::terms(text) {
+                    vector[crate::util::fnv1a(&term) as usize % FAKE_DIMENSIONS] += 1.0;
+                }
+                vector
+            })
+            .collect())
+    }
+}
+
+// Embedding endpoint of the LLM provider, for the providers that offer one
+#[derive(Debug, Clone)]
+pub struct ProviderEmbedder {
+    provider: EmbeddingProvider,
+
+    // Base URL of the provider's API with a trailing slash, e.g. "https://api.openai.com/v1/"
+    base_url: String,
+    credential: Credential,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EmbeddingProvider {
+    OpenAI,
+    Gemini,
+    Ollama,
+}
+
+impl ProviderEmbedder {
+    // Embedder for the provider of the given adapter, if the provider has embeddings.
+    // The base URL is the one requests of the model go to, so custom endpoints
+    // and remote Ollama servers get the embedding requests as well.
+    pub fn for_adapter(
+        adapter_kind: AdapterKind,
+        base_url: &str,
+        credential: Credential,
+    ) -> Option<Self> {
+        let provider = match adapter_kind {
+            AdapterKind::OpenAI => EmbeddingProvider::OpenAI,
+            AdapterKind::Gemini => EmbeddingProvider::Gemini,
+            AdapterKind::Ollama => EmbeddingProvider::Ollama,
+            _ => return None,
+        };
+
+        let mut base_url = base_url.to_string();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+
+        Some(Self {
+            provider,
+            base_url,
+            credential,
+        })
+    }
+}
+
+impl Embedder for ProviderEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, anyhow::Error> {
+        let http = reqwest::Client::new();
+
+        // Ollama serves the OpenAI embeddings API next to the OpenAI chat API
+        let request = match self.provider {
+            EmbeddingProvider::OpenAI | EmbeddingProvider::Ollama => {
+                let model = match self.provider {
+                    EmbeddingProvider::Ollama => "nomic-embed-text",
+                    _ => "text-embedding-3-small",
+                };
+                let request = http
+                    .post(format!("{}embeddings", self.base_url))
+                    .json(&serde_json::json!({ "model": model, "input": texts }));
+
+                // a local Ollama doesn't need a key
+                if self.credential.api_key.is_empty() {
+                    request
+                } else {
+                    request.bearer_auth(&self.credential.api_key)
+                }
+            }
+            EmbeddingProvider::Gemini => http
+                .post(format!(
+                    "{}models/text-embedding-004:batchEmbedContents",
+                    self.base_url
+                ))
+                .query(&[("key", &self.credential.api_key)])
+                .json(&serde_json::json!({
+                    "requests": texts.iter().map(|text| serde_json::json!({
+                        "model": "models/text-embedding-004",
+                        "content": { "parts": [{ "text": text }] },
+                    })).collect::<Vec<serde_json::Value>>(),
+                })),
+        };
+
+        let request = self
+            .credential
+            .headers
+            .iter()
+            .fold(request, |request, (name, value)| {
+                request.header(name, value)
+            });
+
+        let response = request.send().await?.error_for_status()?;
+        let body = response.json::<serde_json::Value>().await?;
+
+        let vectors = match self.provider {
+            EmbeddingProvider::OpenAI | EmbeddingProvider::Ollama => {
+                body["data"].as_array().map(|data| {
+                    data.iter()
+                        .map(|item| item["embedding"].clone())
+                        .collect::<Vec<serde_json::Value>>()
+                })
+            }
+            EmbeddingProvider::Gemini => body["embeddings"].as_array().map(|embeddings| {
+                embeddings
+                    .iter()
+                    .map(|item| item["values"].clone())
+                    .collect::<Vec<serde_json::Value>>()
+            }),
+        };
+
+        vectors
+            .ok_or_else(|| anyhow::anyhow!("No embeddings in response"))?
+            .into_iter()
+            .map(|vector| {
+                serde_json::from_value::<Vec<f32>>(vector)
+                    .map_err(|e| anyhow::anyhow!("Invalid embedding: {}", e))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,152 @@
+use genai::adapter::AdapterKind;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+// Adapters that custom endpoints and credentials can name.
+// Most self-hosted servers and gateways offer an OpenAI compatible API.
+const ADAPTER_KINDS: [AdapterKind; 8] = [
+    AdapterKind::OpenAI,
+    AdapterKind::Ollama,
+    AdapterKind::Anthropic,
+    AdapterKind::Cohere,
+    AdapterKind::Gemini,
+    AdapterKind::Groq,
+    AdapterKind::Xai,
+    AdapterKind::DeepSeek,
+];
+
+// Adapter of endpoints that don't name one
+const DEFAULT_ADAPTER: &str = "openai";
+
+// Global configuration of the custom endpoints
+pub static ENDPOINTS: LazyLock<Endpoints> = LazyLock::new(Endpoints::new);
+
+// Custom endpoints by model name or alias, for self-hosted models
+// and gateways that aren't reached at the default URL of the provider
+pub struct Endpoints {
+    endpoints: Mutex<HashMap<String, Endpoint>>,
+}
+
+impl Endpoints {
+    // Create the endpoints. This function is private to ensure that they are only created once.
+    fn new() -> Self {
+        Self {
+            endpoints: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Endpoint of the given model or alias, if it has a custom endpoint
+    pub fn get(&self, model: &str) -> Option<Endpoint> {
+        self.endpoints.lock().unwrap().get(model).cloned()
+    }
+
+    // Models and aliases with a custom endpoint, sorted
+    pub fn aliases(&self) -> Vec<String> {
+        let mut aliases = self
+            .endpoints
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        aliases.sort();
+        aliases
+    }
+
+    // Replace all custom endpoints
+    pub fn configure(&self, endpoints: HashMap<String, Endpoint>) {
+        *self.endpoints.lock().unwrap() = endpoints;
+    }
+}
+
+// Where and how to reach a model
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    // Base URL of the API, ending with a slash, e.g. "http://localhost:8080/v1/"
+    pub url: String,
+
+    // Lowercase name of the adapter kind that speaks the API of the endpoint
+    pub adapter: String,
+
+    // Name of the model at the endpoint
+    pub model: String,
+
+    // API key for the endpoint, instead of the key of the provider of the adapter
+    pub api_key: Option<String>,
+
+    // Additional headers sent with every request to the endpoint
+    pub headers: Vec<(String, String)>,
+}
+
+// Parse the custom endpoints by model name or alias, see `configure_endpoints`.
+// An empty configuration has no endpoints.
+pub fn parse(json: &str) -> Result<HashMap<String, Endpoint>, anyhow::Error> {
+    if json.trim().is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let value: serde_json::Value = serde_json::from_str(json)?;
+    let aliases = value
+        .as_object()
+        .ok_or_else(|| anyhow::anyhow!("Endpoints must be an object by model name"))?;
+
+    let mut endpoints = HashMap::new();
+    for (alias, endpoint) in aliases {
+        endpoints.insert(alias.clone(), Endpoint::from_json(alias, endpoint)?);
+    }
+
+    Ok(endpoints)
+}
+
+impl Endpoint {
+    fn from_json(alias: &str, value: &serde_json::Value) -> Result<Self, anyhow::Error> {
+        let url = value["url"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing url of endpoint {}", alias))?;
+        let mut url = url::Url::parse(url)
+            .map_err(|e| anyhow::anyhow!("Invalid url of endpoint {}: {}", alias, e))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(anyhow::anyhow!("Endpoint {} is not a http(s) url", alias));
+        }
+        // the adapters append their paths to the base URL
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+
+        let adapter = value["adapter"]
+            .as_str()
+            .unwrap_or(DEFAULT_ADAPTER)
+            .to_lowercase();
+        if adapter_kind(&adapter).is_none() {
+            return Err(anyhow::anyhow!(
+                "Unknown adapter {} of endpoint {}, expected one of {}",
+                adapter,
+                alias,
+                adapter_names().join(", ")
+            ));
+        }
+
+        Ok(Self {
+            url: url.to_string(),
+            adapter,
+            model: value["model"].as_str().unwrap_or(alias).to_string(),
+            api_key: value["api_key"].as_str().map(str::to_string),
+            headers: crate::credentials::headers(value, alias)?,
+        })
+    }
+}
+
+// Adapter kind by its name, case is ignored, e.g. "openai" or "DeepSeek"
+pub fn adapter_kind(name: &str) -> Option<AdapterKind> {
+    ADAPTER_KINDS
+        .into_iter()
+        .find(|adapter_kind| adapter_kind.as_str().eq_ignore_ascii_case(name))
+}
+
+// Lowercase names of the adapters, e.g. "openai"
+pub fn adapter_names() -> Vec<String> {
+    ADAPTER_KINDS
+        .iter()
+        .map(|adapter_kind| adapter_kind.as_str().to_lowercase())
+        .collect()
+}
@@ -0,0 +1,233 @@
+use crate::diagnostics::Cause;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+// Errors of the exports. Every error has a stable code the extension can act on,
+// a message that can be shown to users, and tells whether trying again may help.
+// The details are for the logs, they may contain what the provider answered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SummyError {
+    // The text of the page, document or feed couldn't be extracted
+    Extraction(String),
+    LanguageDetection(String),
+
+    // The provider rejected the credentials, or there are none for it
+    Auth(String),
+
+    // The rate limit or quota of the API key is exceeded
+    RateLimit(String),
+    Timeout(String),
+
+    // The request was aborted before it finished
+    Cancelled(String),
+
+    // The text doesn't fit into the context of the model
+    ContextTooLong(String),
+
+    // The provider doesn't know the model
+    UnknownModel(String),
+
+    // The endpoint of the provider couldn't be reached
+    Network(String),
+
+    // The model answered, but not the way we asked it to
+    InvalidModelOutput(String),
+    SessionNotFound { session_id: String, expired: bool },
+
+    // Invalid arguments or configuration, the details say what's wrong
+    InvalidInput(String),
+
+    // Any other failure of a request to the LLM
+    Llm(String),
+}
+
+impl SummyError {
+    // Error of a failed request to the LLM with the cause `Cause` told
+    pub fn from_cause(cause: Option<Cause>, detail: String) -> Self {
+        match cause {
+            Some(Cause::Cancelled) => SummyError::Cancelled(detail),
+            Some(Cause::Timeout) => SummyError::Timeout(detail),
+            Some(Cause::ContextTooLong) => SummyError::ContextTooLong(detail),
+            Some(Cause::UnknownModel) => SummyError::UnknownModel(detail),
+            Some(Cause::AuthRejected) => SummyError::Auth(detail),
+            Some(Cause::NetworkUnreachable) => SummyError::Network(detail),
+            Some(Cause::QuotaExceeded) => SummyError::RateLimit(detail),
+            Some(Cause::MalformedResponse) => SummyError::InvalidModelOutput(detail),
+            None => SummyError::Llm(detail),
+        }
+    }
+
+    // Error of a failed request to the LLM, telling the cause from the text of the error
+    pub fn from_llm(detail: String) -> Self {
+        Self::from_cause(Cause::classify(&detail), detail)
+    }
+
+    // Error of a failed request to the LLM, telling the cause from the genai error
+    pub fn from_genai(context: &str, error: &genai::Error) -> Self {
+        Self::from_cause(
+            Cause::from_error(error),
+            format!("{}: {:?}", context, error),
+        )
+    }
+
+    // Stable code of the error for the extension, don't change existing codes
+    pub fn code(&self) -> &'static str {
+        match self {
+            SummyError::Extraction(_) => "extraction_failed",
+            SummyError::LanguageDetection(_) => "language_detection_failed",
+            SummyError::Auth(_) => "auth_rejected",
+            SummyError::RateLimit(_) => "rate_limited",
+            SummyError::Timeout(_) => "timeout",
+            SummyError::Cancelled(_) => "cancelled",
+            SummyError::ContextTooLong(_) => "context_too_long",
+            SummyError::UnknownModel(_) => "unknown_model",
+            SummyError::Network(_) => "network_unreachable",
+            SummyError::InvalidModelOutput(_) => "invalid_model_output",
+            SummyError::SessionNotFound { .. } => "session_not_found",
+            SummyError::InvalidInput(_) => "invalid_input",
+            SummyError::Llm(_) => "llm_failed",
+        }
+    }
+
+    // What went wrong in words users understand, and what they can do about it
+    pub fn message(&self) -> String {
+        match self {
+            SummyError::Extraction(_) => "Could not extract the text of the content.".to_string(),
+            SummyError::LanguageDetection(_) => {
+                "Could not detect the language of the content. Please try again.".to_string()
+            }
+            SummyError::Auth(_) => {
+                "The provider rejected the API key. Please verify the API key in the options."
+                    .to_string()
+            }
+            SummyError::RateLimit(_) => {
+                "The rate limit or quota of the API key is exceeded. Please try again later."
+                    .to_string()
+            }
+            SummyError::Timeout(_) => {
+                "The model took too long to answer. Please try again.".to_string()
+            }
+            SummyError::Cancelled(_) => "The request was cancelled.".to_string(),
+            SummyError::ContextTooLong(_) => {
+                "The content is too long for the model. Please choose a model with a larger context."
+                    .to_string()
+            }
+            SummyError::UnknownModel(_) => {
+                "The provider does not know the model. Please verify the model name in the options."
+                    .to_string()
+            }
+            SummyError::Network(_) => {
+                "Could not reach the endpoint. Please verify the endpoint and your network connection."
+                    .to_string()
+            }
+            SummyError::InvalidModelOutput(_) => {
+                "The model did not answer as expected. Please try again.".to_string()
+            }
+            SummyError::SessionNotFound { expired: true, .. } => {
+                "The conversation expired. Please summarize the page again.".to_string()
+            }
+            SummyError::SessionNotFound { expired: false, .. } => {
+                "The conversation was not found. Please summarize the page again.".to_string()
+            }
+            SummyError::InvalidInput(detail) => detail.clone(),
+            SummyError::Llm(_) => {
+                "Could not get an answer from the model. Please verify the model in the options."
+                    .to_string()
+            }
+        }
+    }
+
+    // Whether the same request may succeed when tried again
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            SummyError::LanguageDetection(_)
+                | SummyError::RateLimit(_)
+                | SummyError::Timeout(_)
+                | SummyError::Cancelled(_)
+                | SummyError::Network(_)
+                | SummyError::InvalidModelOutput(_)
+                | SummyError::Llm(_)
+        )
+    }
+
+    pub fn detail(&self) -> String {
+        match self {
+            SummyError::SessionNotFound {
+                session_id,
+                expired: true,
+            } => format!("Session {} expired", session_id),
+            SummyError::SessionNotFound {
+                session_id,
+                expired: false,
+            } => format!("Session {} not found", session_id),
+            SummyError::Extraction(detail)
+            | SummyError::LanguageDetection(detail)
+            | SummyError::Auth(detail)
+            | SummyError::RateLimit(detail)
+            | SummyError::Timeout(detail)
+            | SummyError::Cancelled(detail)
+            | SummyError::ContextTooLong(detail)
+            | SummyError::UnknownModel(detail)
+            | SummyError::Network(detail)
+            | SummyError::InvalidModelOutput(detail)
+            | SummyError::InvalidInput(detail)
+            | SummyError::Llm(detail) => detail.clone(),
+        }
+    }
+}
+
+impl fmt::Display for SummyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.detail())
+    }
+}
+
+impl std::error::Error for SummyError {}
+
+impl SummyError {
+    // The error as JavaScript `Error` with the message for users, and the "code",
+    // "retryable" and "detail" of the error plus the given fields as additional
+    // properties, e.g. what `verify_access` found out before the failure
+    pub fn into_js_with(self, fields: &serde_json::Map<String, serde_json::Value>) -> JsValue {
+        crate::log(&self.to_string());
+
+        let error = JsValue::from(JsError::new(&self.message()));
+        let properties = [
+            ("name", JsValue::from_str("SummyError")),
+            ("code", JsValue::from_str(self.code())),
+            ("retryable", JsValue::from_bool(self.retryable())),
+            ("detail", JsValue::from_str(&self.detail())),
+        ];
+        let fields = fields.iter().map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::Null => JsValue::NULL,
+                serde_json::Value::Bool(value) => JsValue::from_bool(*value),
+                serde_json::Value::Number(value) => {
+                    JsValue::from_f64(value.as_f64().unwrap_or_default())
+                }
+                serde_json::Value::String(value) => JsValue::from_str(value),
+                value => JsValue::from_str(&value.to_string()),
+            };
+            (key.as_str(), value)
+        });
+        for (key, value) in fields.chain(properties) {
+            reflect_set(&error, &JsValue::from_str(key), &value);
+        }
+
+        error
+    }
+}
+
+// Errors reach JavaScript as `Error` objects, see `SummyError::into_js_with`
+impl From<SummyError> for JsValue {
+    fn from(err: SummyError) -> Self {
+        err.into_js_with(&serde_json::Map::new())
+    }
+}
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Reflect, js_name = set)]
+    fn reflect_set(target: &JsValue, key: &JsValue, value: &JsValue) -> bool;
+}
@@ -0,0 +1,136 @@
+use roxmltree::{Document, Node};
+
+// Namespace used by RSS 2.0 feeds for the full HTML body of an item
+const RSS_CONTENT_NS: &str = "http://purl.org/rss/1.0/modules/content/";
+
+// A single entry of an RSS or Atom feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedItem {
+    pub title: String,
+    pub link: String,
+    pub date: Option<String>,
+
+    // The HTML body of the item, this is either the full content
+    // or the description/summary if the feed doesn't provide one
+    pub content: String,
+}
+
+// Parse an RSS 2.0 or Atom feed into its items
+pub fn parse(xml: &str) -> Result<Vec<FeedItem>, anyhow::Error> {
+    let doc = match Document::parse(xml) {
+        Ok(doc) => doc,
+        Err(err) => return Err(anyhow::anyhow!("Error parsing feed: {:?}", err)),
+    };
+
+    let root = doc.root_element();
+    match root.tag_name().name() {
+        "rss" => Ok(parse_rss(root)),
+        "feed" => Ok(parse_atom(root)),
+        other => Err(anyhow::anyhow!("Unsupported feed format: <{}>", other)),
+    }
+}
+
+// RSS 2.0: <rss><channel><item>...</item></channel></rss>
+fn parse_rss(root: Node) -> Vec<FeedItem> {
+    root.descendants()
+        .filter(|node| node.has_tag_name("item"))
+        .map(|item| {
+            // prefer the full body over the description
+            let content = child(item, |n| n.has_tag_name((RSS_CONTENT_NS, "encoded")))
+                .or_else(|| child(item, |n| n.has_tag_name("description")))
+                .map(text)
+                .unwrap_or_default();
+
+            FeedItem {
+                title: child_text(item, "title"),
+                link: child_text(item, "link"),
+                date: child(item, |n| n.has_tag_name("pubDate")).map(text),
+                content,
+            }
+        })
+        .collect()
+}
+
+// Atom: <feed><entry>...</entry></feed>
+fn parse_atom(root: Node) -> Vec<FeedItem> {
+    root.children()
+        .filter(|node| node.has_tag_name("entry"))
+        .map(|entry| {
+            // the alternate link points to the post, a link without
+            // a rel attribute is an alternate link by definition
+            let link = entry
+                .children()
+                .filter(|n| n.has_tag_name("link"))
+                .find(|n| matches!(n.attribute("rel"), None | Some("alternate")))
+                .and_then(|n| n.attribute("href"))
+                .unwrap_or_default()
+                .to_string();
+
+            let content = child(entry, |n| n.has_tag_name("content"))
+                .or_else(|| child(entry, |n| n.has_tag_name("summary")))
+                .map(text)
+                .unwrap_or_default();
+
+            let date = child(entry, |n| n.has_tag_name("published"))
+                .or_else(|| child(entry, |n| n.has_tag_name("updated")))
+                .map(text);
+
+            FeedItem {
+                title: child_text(entry, "title"),
+                link,
+                date,
+                content,
+            }
+        })
+        .collect()
+}
+
+fn child<'a, 'input, P>(node: Node<'a, 'input>, predicate: P) -> Option<Node<'a, 'input>>
+where
+    P: Fn(&Node) -> bool,
+{
+    node.children().find(|n| n.is_element() && predicate(n))
+}
+
+fn child_text(node: Node, name: &str) -> String {
+    child(node, |n| n.has_tag_name(name))
+        .map(text)
+        .unwrap_or_default()
+}
+
+// Concatenate all text and CDATA below the given node
+fn text(node: Node) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// Text of an HTML fragment without its tags, for the short descriptions of feed
+// items that readability drops because they don't look like an article
+pub fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
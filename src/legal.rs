@@ -0,0 +1,170 @@
+// Titles of legal documents, checked at the beginning of a document
+const LEGAL_TITLES: [&str; 9] = [
+    "terms of service",
+    "terms of use",
+    "terms and conditions",
+    "user agreement",
+    "license agreement",
+    "privacy policy",
+    "privacy notice",
+    "privacy statement",
+    "cookie policy",
+];
+
+// Number of characters at the beginning of a text that we check for a title.
+// Legal pages are often linked from navigation or cookie banners, so we
+// only look where the title of the document is expected.
+const TITLE_SEARCH_LENGTH: usize = 200;
+
+// Maximum length of a line we take for a title, longer lines are
+// sentences that just mention a legal document, like a cookie banner
+const MAX_TITLE_LENGTH: usize = 60;
+
+// Number of different risk categories that need to be mentioned
+// for a document without a legal title to be treated as legal text
+const MIN_RISK_CATEGORIES: usize = 5;
+
+// Types of clauses we look for in terms of service and privacy policies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RiskCategory {
+    DataSharing,
+    Arbitration,
+    AutoRenewal,
+    Tracking,
+    ContentLicense,
+    AccountTermination,
+}
+
+impl RiskCategory {
+    pub const ALL: [RiskCategory; 6] = [
+        RiskCategory::DataSharing,
+        RiskCategory::Arbitration,
+        RiskCategory::AutoRenewal,
+        RiskCategory::Tracking,
+        RiskCategory::ContentLicense,
+        RiskCategory::AccountTermination,
+    ];
+
+    // Name of the category as used in the JSON schema
+    pub fn name(&self) -> &'static str {
+        match self {
+            RiskCategory::DataSharing => "data_sharing",
+            RiskCategory::Arbitration => "arbitration",
+            RiskCategory::AutoRenewal => "auto_renewal",
+            RiskCategory::Tracking => "tracking",
+            RiskCategory::ContentLicense => "content_license",
+            RiskCategory::AccountTermination => "account_termination",
+        }
+    }
+
+    // Phrases that indicate a clause of this category. Single words like
+    // "cookies" or "renewal" show up in regular articles too, so we only
+    // use phrases that are typical for the wording of legal clauses.
+    fn keywords(&self) -> &'static [&'static str] {
+        match self {
+            RiskCategory::DataSharing => &[
+                "with third parties",
+                "share your personal",
+                "sell your personal",
+                "disclose your personal",
+                "with our business partners",
+            ],
+            RiskCategory::Arbitration => &[
+                "binding arbitration",
+                "class action waiver",
+                "waive your right",
+                "right to a jury trial",
+            ],
+            RiskCategory::AutoRenewal => &[
+                "automatically renew",
+                "renew automatically",
+                "recurring billing",
+                "until you cancel",
+            ],
+            RiskCategory::Tracking => &[
+                "we use cookies",
+                "web beacons",
+                "tracking technologies",
+                "collect location data",
+                "device identifiers",
+            ],
+            RiskCategory::ContentLicense => &[
+                "royalty-free license",
+                "worldwide license",
+                "irrevocable license",
+                "right to sublicense",
+            ],
+            RiskCategory::AccountTermination => &[
+                "terminate your account",
+                "suspend your account",
+                "at our sole discretion",
+                "without prior notice",
+            ],
+        }
+    }
+}
+
+// Check whether a document looks like terms of service or a privacy policy.
+// The raw content is checked for an HTML title, the extracted text for a
+// title line at its beginning or clauses of most risk categories.
+pub fn detect(raw: &str, text: &str) -> bool {
+    let title = crate::content::html_title(raw)
+        .unwrap_or_default()
+        .to_lowercase();
+    if LEGAL_TITLES.iter().any(|legal| title.contains(legal)) {
+        return true;
+    }
+
+    let head = text
+        .chars()
+        .take(TITLE_SEARCH_LENGTH)
+        .collect::<String>()
+        .to_lowercase();
+    let titled = head
+        .lines()
+        .map(|line| line.trim().trim_start_matches('#').trim())
+        .filter(|line| line.len() <= MAX_TITLE_LENGTH)
+        .any(|line| LEGAL_TITLES.iter().any(|legal| line.starts_with(legal)));
+
+    titled || categories(text).len() >= MIN_RISK_CATEGORIES
+}
+
+// Risk categories mentioned anywhere in the text
+pub fn categories(text: &str) -> Vec<RiskCategory> {
+    let lower = text.to_lowercase();
+    RiskCategory::ALL
+        .into_iter()
+        .filter(|category| category.keywords().iter().any(|k| lower.contains(k)))
+        .collect()
+}
+
+// Mark each risk item of the LLM response as verified if its quote can be found in
+// the source text. Models tend to paraphrase, so users should know which quotes are
+// real. Responses that aren't valid JSON are returned unchanged.
+pub fn verify_quotes(response: &str, text: &str) -> String {
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(response) else {
+        return response.to_string();
+    };
+
+    let source = normalize(text);
+    if let Some(risks) = value.get_mut("risks").and_then(|r| r.as_array_mut()) {
+        for risk in risks.iter_mut().filter_map(|r| r.as_object_mut()) {
+            let quote = risk.get("quote").and_then(|q| q.as_str()).map(normalize);
+            let verified = matches!(quote, Some(q) if !q.is_empty() && source.contains(&q));
+            risk.insert("verified".to_string(), serde_json::Value::Bool(verified));
+        }
+    }
+
+    value.to_string()
+}
+
+// Lowercase, collapse whitespace and ignore quotation marks for comparison
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+        .replace(['"', '\'', '“', '”', '‘', '’'], "")
+        .trim_matches(|c: char| c == '.' || c == '…' || c.is_whitespace())
+        .to_string()
+}
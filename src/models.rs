@@ -0,0 +1,149 @@
+// Models we suggest for providers whose model listing we can't query,
+// by the lowercase name of the adapter kind, see `endpoints::adapter_names`
+const KNOWN_MODELS: [(&str, &[&str]); 8] = [
+    (
+        "gemini",
+        &[
+            "gemini-2.0-flash-lite",
+            "gemini-2.0-flash",
+            "gemini-1.5-pro",
+            "gemini-1.5-flash",
+            "gemini-1.5-flash-8b",
+        ],
+    ),
+    (
+        "anthropic",
+        &[
+            "claude-3-7-sonnet-latest",
+            "claude-3-5-haiku-latest",
+            "claude-3-opus-20240229",
+            "claude-3-haiku-20240307",
+        ],
+    ),
+    ("deepseek", &["deepseek-chat", "deepseek-reasoner"]),
+    (
+        "openai",
+        &[
+            "gpt-4.1",
+            "gpt-4.1-mini",
+            "gpt-4o",
+            "gpt-4o-mini",
+            "o3-mini",
+            "o1",
+            "o1-mini",
+        ],
+    ),
+    (
+        "groq",
+        &[
+            "llama-3.3-70b-versatile",
+            "llama-3.1-8b-instant",
+            "llama-3.2-11b-vision-preview",
+            "llama-3.2-90b-vision-preview",
+            "deepseek-r1-distill-llama-70b",
+            "mixtral-8x7b-32768",
+            "gemma2-9b-it",
+        ],
+    ),
+    ("xai", &["grok-2-latest", "grok-2-vision-latest"]),
+    (
+        "cohere",
+        &["command-r-plus", "command-r", "command-r7b-12-2024"],
+    ),
+    (
+        "ollama",
+        &["llama3.2", "llama3.1", "mistral", "gemma2", "llava"],
+    ),
+];
+
+// Parts of the names of models that can't chat, like the embedding
+// and speech models that show up in the listings of the providers
+const NON_CHAT_MARKERS: [&str; 11] = [
+    "embed",
+    "whisper",
+    "tts",
+    "dall-e",
+    "audio",
+    "realtime",
+    "moderation",
+    "transcribe",
+    "image",
+    "guard",
+    "search",
+];
+
+// Prefixes of the names of models that understand images, besides
+// the models that have "vision" or "llava" in their name
+const VISION_PREFIXES: [&str; 8] = [
+    "gemini-1.5",
+    "gemini-2",
+    "claude-3",
+    "gpt-4o",
+    "gpt-4.1",
+    "gpt-4-turbo",
+    "o1-2",
+    "llama-4",
+];
+
+// What a model can do, as far as we can tell from its name
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    pub name: String,
+
+    // Replies can be constrained by a JSON schema, otherwise we ask for plain JSON
+    pub json_schema: bool,
+
+    pub streaming: bool,
+
+    // Context size in tokens, see `tokens::context_size`
+    pub context_size: usize,
+
+    // The model understands images
+    pub vision: bool,
+}
+
+impl ModelInfo {
+    pub fn new(adapter: &str, name: &str) -> Self {
+        let lower = name.to_lowercase();
+        Self {
+            name: name.to_string(),
+            json_schema: supports_json_schema(adapter),
+            // genai streams the replies of all adapters
+            streaming: true,
+            context_size: crate::tokens::context_size(name),
+            vision: lower.contains("vision")
+                || lower.contains("llava")
+                || lower == "o1"
+                || VISION_PREFIXES
+                    .iter()
+                    .any(|prefix| lower.starts_with(prefix)),
+        }
+    }
+}
+
+// Whether the adapter can constrain replies by a JSON schema.
+// Groq and Ollama do currently only support plain JSON replies.
+pub fn supports_json_schema(adapter: &str) -> bool {
+    !matches!(adapter, "groq" | "ollama")
+}
+
+// The models we suggest for the adapter when we can't query its listing
+pub fn known(adapter: &str) -> Vec<String> {
+    KNOWN_MODELS
+        .iter()
+        .find(|(name, _)| *name == adapter)
+        .map(|(_, models)| models.iter().map(|model| model.to_string()).collect())
+        .unwrap_or_default()
+}
+
+// Names of the chat models among the names listed by the adapter, sorted.
+// Listings include the embedding and speech models of the provider too.
+pub fn chat_models(mut names: Vec<String>) -> Vec<String> {
+    names.retain(|name| {
+        let name = name.to_lowercase();
+        !NON_CHAT_MARKERS.iter().any(|marker| name.contains(marker))
+    });
+    names.sort();
+    names.dedup();
+    names
+}
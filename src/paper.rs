@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+// Number of most cited references we point the LLM to
+const MAX_CITED_REFERENCES: usize = 5;
+
+// Section names commonly used in papers and reports
+const KNOWN_SECTIONS: [&str; 18] = [
+    "abstract",
+    "introduction",
+    "background",
+    "related work",
+    "method",
+    "methods",
+    "methodology",
+    "materials and methods",
+    "experiments",
+    "results",
+    "discussion",
+    "evaluation",
+    "conclusion",
+    "conclusions",
+    "limitations",
+    "acknowledgements",
+    "acknowledgments",
+    "references",
+];
+
+// Sections a research paper is expected to have, we need at least
+// a few of them before we treat a document without metadata as a paper
+const PAPER_SECTIONS: [&str; 5] = ["abstract", "method", "results", "conclusion", "references"];
+
+// Meta tags used by publishers and preprint servers. Links to papers, e.g. to
+// "arxiv.org/abs/", don't count, any page may link to a paper.
+const PAPER_MARKERS: [&str; 4] = [
+    "name=\"citation_title\"",
+    "name=\"citation_author\"",
+    "name=\"citation_doi\"",
+    "name=\"citation_arxiv_id\"",
+];
+
+// A section of a paper
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub heading: String,
+    pub body: String,
+}
+
+// A reference and how often it is cited in the paper
+#[derive(Debug, Clone, PartialEq)]
+pub struct CitedReference {
+    pub number: u32,
+    pub text: String,
+    pub count: usize,
+}
+
+// Check whether a document looks like a research paper. The raw content
+// is checked for citation meta tags, the extracted text for typical sections.
+pub fn detect(raw: &str, text: &str) -> bool {
+    if PAPER_MARKERS.iter().any(|marker| raw.contains(marker)) {
+        return true;
+    }
+
+    let headings = sections(text)
+        .into_iter()
+        .map(|section| section_name(&section.heading))
+        .collect::<Vec<String>>();
+
+    let found = PAPER_SECTIONS
+        .iter()
+        .filter(|name| headings.iter().any(|h| h.starts_with(*name)))
+        .count();
+
+    found >= 3
+}
+
+// Known section names, optionally numbered, e.g. "3.1 Results" or "IV. DISCUSSION"
+pub fn is_section_name(text: &str) -> bool {
+    KNOWN_SECTIONS.contains(&section_name(text).as_str())
+}
+
+// Split the text into sections at lines that are known section headings.
+// Text before the first heading ends up in a section without heading.
+pub fn sections(text: &str) -> Vec<Section> {
+    let mut sections = vec![Section {
+        heading: String::new(),
+        body: String::new(),
+    }];
+
+    for line in text.lines() {
+        let trimmed = line.trim().trim_start_matches('#').trim();
+
+        if is_section_name(trimmed) {
+            sections.push(Section {
+                heading: trimmed.to_string(),
+                body: String::new(),
+            });
+            continue;
+        }
+
+        let current = sections.last_mut().unwrap();
+        if !current.body.is_empty() {
+            current.body.push('\n');
+        }
+        current.body.push_str(line);
+    }
+
+    sections
+        .into_iter()
+        .filter(|s| !s.heading.is_empty() || !s.body.trim().is_empty())
+        .collect()
+}
+
+// Render the paper for the LLM, with explicit section headings and
+// the list of the most cited references appended
+pub fn to_text(text: &str) -> String {
+    let sections = sections(text);
+
+    let mut out = String::new();
+    for section in &sections {
+        if !section.heading.is_empty() {
+            out.push_str(&format!("## {}\n", section.heading));
+        }
+        out.push_str(section.body.trim());
+        out.push_str("\n\n");
+    }
+
+    let cited = most_cited(&sections, MAX_CITED_REFERENCES);
+    if !cited.is_empty() {
+        out.push_str("## Most cited references\n");
+        for reference in cited {
+            out.push_str(&format!(
+                "[{}] (cited {} times) {}\n",
+                reference.number, reference.count, reference.text
+            ));
+        }
+    }
+
+    out.trim().to_string()
+}
+
+// Count numeric citations like "[3]", "[1, 4]" or "[2-5]" outside of the
+// references section and match them with the entries of the references section
+pub fn most_cited(sections: &[Section], n: usize) -> Vec<CitedReference> {
+    let (references, body): (Vec<&Section>, Vec<&Section>) = sections
+        .iter()
+        .partition(|s| section_name(&s.heading) == "references");
+
+    let entries = references
+        .iter()
+        .flat_map(|s| reference_entries(&s.body))
+        .collect::<HashMap<u32, String>>();
+
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for section in body {
+        for number in citations(&section.body) {
+            if entries.contains_key(&number) {
+                *counts.entry(number).or_default() += 1;
+            }
+        }
+    }
+
+    let mut cited = counts
+        .into_iter()
+        .map(|(number, count)| CitedReference {
+            number,
+            text: entries[&number].clone(),
+            count,
+        })
+        .collect::<Vec<CitedReference>>();
+
+    // most cited first, ties in order of the reference list
+    cited.sort_by(|a, b| b.count.cmp(&a.count).then(a.number.cmp(&b.number)));
+    cited.truncate(n);
+    cited
+}
+
+// Lowercase section name without numbering. Arabic or roman numbering
+// only counts when a dot or whitespace follows, so "Introduction" keeps its "I".
+fn section_name(heading: &str) -> String {
+    let heading = heading.trim();
+    let numbering = match heading.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(0) => heading.find(|c: char| !"IVX".contains(c)).unwrap_or(0),
+        Some(end) => end,
+        None => 0,
+    };
+
+    let rest = &heading[numbering..];
+    let name = if numbering > 0 && rest.starts_with(|c: char| c == '.' || c.is_whitespace()) {
+        rest.trim_start_matches(|c: char| c == '.' || c.is_whitespace())
+    } else {
+        heading
+    };
+
+    name.trim().to_lowercase()
+}
+
+// All bracketed numeric citations in the text, ranges are expanded
+fn citations(text: &str) -> Vec<u32> {
+    let mut numbers = Vec::new();
+
+    for (start, _) in text.match_indices('[') {
+        let Some(len) = text[start + 1..].find(']') else {
+            continue;
+        };
+        let inner = &text[start + 1..start + 1 + len];
+
+        // only digits, separators and ranges are allowed in a citation
+        if inner.is_empty()
+            || !inner
+                .chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, ',' | ' ' | '-' | '–'))
+        {
+            continue;
+        }
+
+        for part in inner.split(',') {
+            let bounds = part
+                .split(['-', '–'])
+                .filter_map(|n| n.trim().parse::<u32>().ok())
+                .collect::<Vec<u32>>();
+
+            match bounds.as_slice() {
+                [n] => numbers.push(*n),
+                // guard against bogus ranges
+                [from, to] if from <= to && to - from < 50 => numbers.extend(*from..=*to),
+                _ => {}
+            }
+        }
+    }
+
+    numbers
+}
+
+// Entries of a references section, each starting with a "[n]" marker
+fn reference_entries(text: &str) -> Vec<(u32, String)> {
+    let markers = text
+        .match_indices('[')
+        .filter_map(|(start, _)| {
+            let len = text[start + 1..].find(']')?;
+            let number = text[start + 1..start + 1 + len].parse::<u32>().ok()?;
+            Some((start, start + len + 2, number))
+        })
+        .collect::<Vec<(usize, usize, u32)>>();
+
+    markers
+        .iter()
+        .enumerate()
+        .map(|(i, (_, end, number))| {
+            let next = markers.get(i + 1).map(|m| m.0).unwrap_or(text.len());
+            let entry = text[*end..next]
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" ");
+            (*number, entry)
+        })
+        .collect()
+}
@@ -0,0 +1,579 @@
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Encoding, Object, ObjectId};
+use std::collections::{BTreeMap, HashMap};
+
+// Default page width in points (US letter) if a page doesn't specify a MediaBox
+const DEFAULT_PAGE_WIDTH: f32 = 612.0;
+
+// Approximate glyph width relative to the font size. We don't parse font
+// metrics, this is only used to estimate where a text fragment ends.
+const AVG_GLYPH_WIDTH: f32 = 0.5;
+
+// Lines repeated on at least this share of pages are considered
+// running headers or footers and are removed from the text
+const RUNNING_LINE_PAGE_SHARE: f32 = 0.5;
+
+// Text content of a PDF document, in reading order
+#[derive(Debug, Clone, Default)]
+pub struct PdfDocument {
+    pub title: Option<String>,
+    pub pages: Vec<PdfPage>,
+    pub sections: Vec<PdfSection>,
+}
+
+// A single page with its paragraphs
+#[derive(Debug, Clone, Default)]
+pub struct PdfPage {
+    pub number: u32,
+    pub paragraphs: Vec<String>,
+}
+
+// A section heading and the page it starts on
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfSection {
+    pub title: String,
+    pub page: u32,
+}
+
+impl PdfDocument {
+    // Render the document as plain text for the LLM. Each page is prefixed
+    // with a `[p. N]` marker so answers can cite the page a statement is from,
+    // section headings are prefixed with `##`.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+
+        if let Some(title) = &self.title {
+            out.push_str(&format!("# {}\n\n", title));
+        }
+
+        for page in &self.pages {
+            if page.paragraphs.is_empty() {
+                continue;
+            }
+
+            out.push_str(&format!("[p. {}]\n", page.number));
+            for paragraph in &page.paragraphs {
+                // the title is already at the top
+                if page.number == 1 && Some(paragraph) == self.title.as_ref() {
+                    continue;
+                }
+
+                let is_section = self
+                    .sections
+                    .iter()
+                    .any(|s| s.page == page.number && &s.title == paragraph);
+
+                if is_section {
+                    out.push_str("## ");
+                }
+                out.push_str(paragraph);
+                out.push_str("\n\n");
+            }
+        }
+
+        out.trim().to_string()
+    }
+}
+
+// Whether the line is a page marker like "[p. 4]" of `PdfDocument::to_text`
+pub fn is_page_marker(line: &str) -> bool {
+    line.strip_prefix("[p. ")
+        .and_then(|rest| rest.strip_suffix(']'))
+        .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+}
+
+// Parse the given PDF and extract its text
+pub fn parse(bytes: &[u8]) -> Result<PdfDocument, anyhow::Error> {
+    let doc = match Document::load_mem(bytes) {
+        Ok(doc) => doc,
+        Err(err) => return Err(anyhow::anyhow!("Error parsing PDF: {:?}", err)),
+    };
+
+    if doc.is_encrypted() {
+        return Err(anyhow::anyhow!("Encrypted PDFs are not supported"));
+    }
+
+    let mut pages = Vec::new();
+    for (number, page_id) in doc.get_pages() {
+        let lines = match page_lines(&doc, page_id) {
+            Ok(lines) if !lines.is_empty() => lines,
+            // fall back to the plain extractor if we can't position the text
+            _ => doc
+                .extract_text(&[number])
+                .unwrap_or_default()
+                .lines()
+                .map(|text| Line::unpositioned(text.trim()))
+                .filter(|line| !line.text.is_empty())
+                .collect(),
+        };
+        pages.push((number, lines));
+    }
+
+    remove_running_lines(&mut pages);
+
+    let body_size = body_font_size(&pages);
+    let title = info_title(&doc).or_else(|| largest_line(&pages));
+
+    let mut sections = Vec::new();
+    let pages = pages
+        .into_iter()
+        .map(|(number, lines)| {
+            let paragraphs = paragraphs(&lines, body_size);
+            for paragraph in &paragraphs {
+                if is_heading(paragraph, &lines, body_size) && Some(paragraph) != title.as_ref() {
+                    sections.push(PdfSection {
+                        title: paragraph.clone(),
+                        page: number,
+                    });
+                }
+            }
+            PdfPage { number, paragraphs }
+        })
+        .collect();
+
+    Ok(PdfDocument {
+        title,
+        pages,
+        sections,
+    })
+}
+
+// A line of text with its position on the page
+#[derive(Debug, Clone)]
+struct Line {
+    text: String,
+    x: f32,
+    y: f32,
+    size: f32,
+}
+
+impl Line {
+    fn unpositioned(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            x: 0.0,
+            y: 0.0,
+            size: 0.0,
+        }
+    }
+}
+
+// A piece of text as drawn by a single text showing operator
+#[derive(Debug, Clone)]
+struct Fragment {
+    text: String,
+    x: f32,
+    y: f32,
+    size: f32,
+}
+
+impl Fragment {
+    fn end(&self) -> f32 {
+        self.x + self.text.chars().count() as f32 * self.size * AVG_GLYPH_WIDTH
+    }
+}
+
+// 2D affine transformation as used by PDF: [a b c d e f]
+#[derive(Debug, Clone, Copy)]
+struct Matrix([f32; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn translate(tx: f32, ty: f32) -> Self {
+        Matrix([1.0, 0.0, 0.0, 1.0, tx, ty])
+    }
+
+    fn multiply(&self, other: &Matrix) -> Self {
+        let [a, b, c, d, e, f] = self.0;
+        let [oa, ob, oc, od, oe, of] = other.0;
+        Matrix([
+            a * oa + b * oc,
+            a * ob + b * od,
+            c * oa + d * oc,
+            c * ob + d * od,
+            e * oa + f * oc + oe,
+            e * ob + f * od + of,
+        ])
+    }
+
+    fn scale(&self) -> f32 {
+        let [a, b, c, d, _, _] = self.0;
+        (a * d - b * c).abs().sqrt()
+    }
+}
+
+// Extract the lines of a page in reading order
+fn page_lines(doc: &Document, page_id: ObjectId) -> Result<Vec<Line>, anyhow::Error> {
+    let fragments = page_fragments(doc, page_id)?;
+    let width = page_width(doc, page_id);
+
+    Ok(reading_order(rows(fragments), width))
+}
+
+// Walk the content stream of a page and collect the positioned text fragments
+fn page_fragments(doc: &Document, page_id: ObjectId) -> Result<Vec<Fragment>, anyhow::Error> {
+    let encodings = doc
+        .get_page_fonts(page_id)?
+        .into_iter()
+        .filter_map(|(name, font)| font.get_font_encoding(doc).ok().map(|enc| (name, enc)))
+        .collect::<BTreeMap<Vec<u8>, Encoding>>();
+
+    let content = Content::decode(&doc.get_page_content(page_id)?)?;
+
+    let mut fragments = Vec::new();
+    let mut ctm = Matrix::IDENTITY;
+    let mut ctm_stack = Vec::new();
+    let mut tm = Matrix::IDENTITY;
+    let mut tlm = Matrix::IDENTITY;
+    let mut leading = 0.0;
+    let mut font_size = 0.0;
+    let mut encoding = None;
+
+    for op in &content.operations {
+        let num = |i: usize| -> f32 {
+            op.operands
+                .get(i)
+                .and_then(|o| o.as_float().ok())
+                .unwrap_or(0.0)
+        };
+
+        match op.operator.as_str() {
+            "q" => ctm_stack.push(ctm),
+            "Q" => ctm = ctm_stack.pop().unwrap_or(Matrix::IDENTITY),
+            "cm" => ctm = Matrix([num(0), num(1), num(2), num(3), num(4), num(5)]).multiply(&ctm),
+            "BT" => {
+                tm = Matrix::IDENTITY;
+                tlm = Matrix::IDENTITY;
+            }
+            "Tf" => {
+                encoding = op
+                    .operands
+                    .first()
+                    .and_then(|o| o.as_name().ok())
+                    .and_then(|name| encodings.get(name));
+                font_size = num(1);
+            }
+            "TL" => leading = num(0),
+            "Td" | "TD" => {
+                if op.operator == "TD" {
+                    leading = -num(1);
+                }
+                tlm = Matrix::translate(num(0), num(1)).multiply(&tlm);
+                tm = tlm;
+            }
+            "Tm" => {
+                tlm = Matrix([num(0), num(1), num(2), num(3), num(4), num(5)]);
+                tm = tlm;
+            }
+            "T*" | "'" | "\"" => {
+                tlm = Matrix::translate(0.0, -leading).multiply(&tlm);
+                tm = tlm;
+            }
+            _ => {}
+        }
+
+        if !matches!(op.operator.as_str(), "Tj" | "TJ" | "'" | "\"") {
+            continue;
+        }
+
+        let Some(encoding) = encoding else {
+            continue;
+        };
+
+        let text = decode_operands(encoding, &op.operands);
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let m = tm.multiply(&ctm);
+        let fragment = Fragment {
+            text,
+            x: m.0[4],
+            y: m.0[5],
+            size: font_size * m.scale(),
+        };
+
+        // we don't know the real glyph widths, so we advance by an estimate
+        let advance = fragment.end() - fragment.x;
+        tm = Matrix::translate(advance / m.scale().max(f32::EPSILON), 0.0).multiply(&tm);
+
+        fragments.push(fragment);
+    }
+
+    Ok(fragments)
+}
+
+fn decode_operands(encoding: &Encoding, operands: &[Object]) -> String {
+    let mut text = String::new();
+    for operand in operands {
+        match operand {
+            Object::String(bytes, _) => {
+                text.push_str(&Document::decode_text(encoding, bytes).unwrap_or_default())
+            }
+            Object::Array(items) => text.push_str(&decode_operands(encoding, items)),
+            // large negative kerning in a TJ array is used as word spacing
+            Object::Integer(i) if *i < -100 => text.push(' '),
+            Object::Real(r) if *r < -100.0 => text.push(' '),
+            _ => {}
+        }
+    }
+    text
+}
+
+// Group fragments on the same baseline into rows, sorted from top to bottom
+fn rows(mut fragments: Vec<Fragment>) -> Vec<Vec<Fragment>> {
+    fragments.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+
+    let mut rows: Vec<Vec<Fragment>> = Vec::new();
+    for fragment in fragments {
+        match rows.last_mut() {
+            Some(row) if (row[0].y - fragment.y).abs() <= row[0].size.max(1.0) * 0.5 => {
+                row.push(fragment)
+            }
+            _ => rows.push(vec![fragment]),
+        }
+    }
+
+    for row in rows.iter_mut() {
+        row.sort_by(|a, b| a.x.total_cmp(&b.x));
+    }
+
+    rows
+}
+
+// Order rows into lines, reading the left column before the right column
+// for pages with a two-column layout
+fn reading_order(rows: Vec<Vec<Fragment>>, width: f32) -> Vec<Line> {
+    let middle = width / 2.0;
+
+    let mut lines = Vec::new();
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+
+    for row in rows {
+        for segment in segments(row) {
+            let start = segment.first().map(|f| f.x).unwrap_or_default();
+            let end = segment.last().map(|f| f.end()).unwrap_or_default();
+            let line = join(segment);
+
+            if end <= middle + line.size {
+                left.push(line);
+            } else if start >= middle - line.size {
+                right.push(line);
+            } else {
+                // a full width line ends the current column block
+                lines.append(&mut left);
+                lines.append(&mut right);
+                lines.push(line);
+            }
+        }
+    }
+
+    lines.append(&mut left);
+    lines.append(&mut right);
+    lines
+}
+
+// Split a row at large horizontal gaps, e.g. between two columns
+fn segments(row: Vec<Fragment>) -> Vec<Vec<Fragment>> {
+    let mut segments: Vec<Vec<Fragment>> = Vec::new();
+    for fragment in row {
+        match segments
+            .last_mut()
+            .and_then(|s| s.last().map(|l| (l.end(), l.size)))
+        {
+            Some((end, size)) if fragment.x - end <= size * 2.0 => {
+                segments.last_mut().unwrap().push(fragment)
+            }
+            _ => segments.push(vec![fragment]),
+        }
+    }
+    segments
+}
+
+fn join(segment: Vec<Fragment>) -> Line {
+    let mut text = String::new();
+    let mut previous_end: Option<f32> = None;
+
+    for fragment in &segment {
+        if let Some(end) = previous_end {
+            let gap = fragment.x - end;
+            if gap > fragment.size * 0.1 && !text.ends_with(' ') && !fragment.text.starts_with(' ')
+            {
+                text.push(' ');
+            }
+        }
+        text.push_str(&fragment.text);
+        previous_end = Some(fragment.end());
+    }
+
+    let size = segment.iter().map(|f| f.size).fold(0.0, f32::max);
+    Line {
+        text: text.split_whitespace().collect::<Vec<&str>>().join(" "),
+        x: segment.first().map(|f| f.x).unwrap_or_default(),
+        y: segment.first().map(|f| f.y).unwrap_or_default(),
+        size,
+    }
+}
+
+// Remove page numbers as well as headers and footers repeated across pages
+fn remove_running_lines(pages: &mut [(u32, Vec<Line>)]) {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for (_, lines) in pages.iter() {
+        let mut seen = lines
+            .iter()
+            .map(|l| normalize(&l.text))
+            .collect::<Vec<String>>();
+        seen.sort();
+        seen.dedup();
+        for text in seen {
+            *counts.entry(text).or_default() += 1;
+        }
+    }
+
+    // running lines can only be told apart from content on longer documents
+    let threshold = (pages.len() as f32 * RUNNING_LINE_PAGE_SHARE).ceil() as usize;
+    let detect_running = pages.len() >= 3;
+
+    for (_, lines) in pages.iter_mut() {
+        lines.retain(|line| {
+            let is_page_number = line.text.trim().chars().all(|c| c.is_ascii_digit());
+            let is_running = detect_running && counts[&normalize(&line.text)] >= threshold;
+            !is_page_number && !is_running
+        });
+    }
+}
+
+// Normalize a line for comparison across pages, ignoring digits like page numbers
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_ascii_digit() && !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+// The most common font size, weighted by the amount of text
+fn body_font_size(pages: &[(u32, Vec<Line>)]) -> f32 {
+    let mut sizes: HashMap<i32, usize> = HashMap::new();
+    for (_, lines) in pages {
+        for line in lines {
+            *sizes.entry((line.size * 10.0).round() as i32).or_default() += line.text.len();
+        }
+    }
+
+    sizes
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(size, _)| size as f32 / 10.0)
+        .unwrap_or_default()
+}
+
+// Title from the document information dictionary
+fn info_title(doc: &Document) -> Option<String> {
+    let info = doc.trailer.get(b"Info").ok()?;
+    let (_, info) = doc.dereference(info).ok()?;
+    let title = info.as_dict().ok()?.get(b"Title").ok()?;
+    let title = lopdf::decode_text_string(title).ok()?;
+    let title = title.trim();
+
+    if title.is_empty() {
+        None
+    } else {
+        Some(title.to_string())
+    }
+}
+
+// The line with the largest font on the first page is most likely the title
+fn largest_line(pages: &[(u32, Vec<Line>)]) -> Option<String> {
+    let (_, lines) = pages.first()?;
+    lines
+        .iter()
+        .filter(|line| line.size > 0.0)
+        .max_by(|a, b| a.size.total_cmp(&b.size))
+        .map(|line| line.text.clone())
+}
+
+// Merge lines into paragraphs. A paragraph ends at a larger vertical gap,
+// a change in font size or before and after a heading.
+fn paragraphs(lines: &[Line], body_size: f32) -> Vec<String> {
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut previous: Option<&Line> = None;
+
+    for line in lines {
+        let heading = looks_like_heading(line, body_size);
+
+        let breaks = match previous {
+            None => false,
+            Some(prev) => {
+                let gap = prev.y - line.y;
+                heading
+                    || looks_like_heading(prev, body_size)
+                    || (prev.size - line.size).abs() > 0.5
+                    || (prev.size > 0.0 && (gap > prev.size * 1.8 || gap < 0.0))
+            }
+        };
+
+        if breaks && !current.is_empty() {
+            paragraphs.push(std::mem::take(&mut current));
+        }
+
+        // join words hyphenated across line breaks
+        if current.ends_with('-') && line.text.starts_with(char::is_lowercase) {
+            current.pop();
+        } else if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&line.text);
+
+        previous = Some(line);
+    }
+
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+
+    paragraphs
+}
+
+fn looks_like_heading(line: &Line, body_size: f32) -> bool {
+    let text = line.text.trim();
+    if text.is_empty() || text.len() > 100 || text.ends_with('.') || text.ends_with(',') {
+        return false;
+    }
+
+    let larger = body_size > 0.0 && line.size > body_size * 1.15;
+    larger || crate::paper::is_section_name(text)
+}
+
+fn is_heading(paragraph: &str, lines: &[Line], body_size: f32) -> bool {
+    lines
+        .iter()
+        .any(|line| line.text == paragraph && looks_like_heading(line, body_size))
+}
+
+// Width of a page, MediaBox may be inherited from the parent page tree node
+fn page_width(doc: &Document, page_id: ObjectId) -> f32 {
+    let mut dict: Option<&Dictionary> = doc.get_dictionary(page_id).ok();
+
+    while let Some(current) = dict {
+        if let Ok(Object::Array(mediabox)) = current.get(b"MediaBox") {
+            let coords = mediabox
+                .iter()
+                .filter_map(|o| o.as_float().ok())
+                .collect::<Vec<f32>>();
+            if coords.len() == 4 {
+                return (coords[2] - coords[0]).abs();
+            }
+        }
+
+        dict = current
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .ok();
+    }
+
+    DEFAULT_PAGE_WIDTH
+}
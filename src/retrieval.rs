@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+// Target size of a chunk in tokens
+const CHUNK_TOKENS: usize = 200;
+
+// BM25 term frequency saturation
+const K1: f32 = 1.2;
+
+// BM25 document length normalization
+const B: f32 = 0.75;
+
+// BM25 index over the chunks of a document
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    chunks: Vec<String>,
+
+    // Term frequencies per chunk
+    terms: Vec<HashMap<String, u32>>,
+
+    // Number of chunks each term appears in
+    chunk_freqs: HashMap<String, u32>,
+
+    // Number of terms per chunk
+    lengths: Vec<usize>,
+    avg_length: f32,
+}
+
+impl Index {
+    // Split the text into chunks and index them
+    pub fn new(text: &str) -> Self {
+        let chunks = chunk(text, CHUNK_TOKENS);
+
+        let terms = chunks
+            .iter()
+            .map(|chunk| {
+                let mut freqs: HashMap<String, u32> = HashMap::new();
+                for term in terms(chunk) {
+                    *freqs.entry(term).or_default() += 1;
+                }
+                freqs
+            })
+            .collect::<Vec<HashMap<String, u32>>>();
+
+        let mut chunk_freqs: HashMap<String, u32> = HashMap::new();
+        for freqs in &terms {
+            for term in freqs.keys() {
+                *chunk_freqs.entry(term.clone()).or_default() += 1;
+            }
+        }
+
+        let lengths = terms
+            .iter()
+            .map(|freqs| freqs.values().sum::<u32>() as usize)
+            .collect::<Vec<usize>>();
+        let avg_length = lengths.iter().sum::<usize>() as f32 / lengths.len().max(1) as f32;
+
+        Self {
+            chunks,
+            terms,
+            chunk_freqs,
+            lengths,
+            avg_length,
+        }
+    }
+
+    pub fn chunks(&self) -> &[String] {
+        &self.chunks
+    }
+
+    // Approximate memory used by the index in bytes
+    pub fn bytes(&self) -> usize {
+        let entry = std::mem::size_of::<u32>() + std::mem::size_of::<String>();
+        let terms = |freqs: &HashMap<String, u32>| {
+            freqs.keys().map(|term| term.len() + entry).sum::<usize>()
+        };
+
+        self.chunks.iter().map(String::len).sum::<usize>()
+            + self.terms.iter().map(terms).sum::<usize>()
+            + terms(&self.chunk_freqs)
+            + self.lengths.len() * std::mem::size_of::<usize>()
+    }
+
+    // Indices of the k chunks most relevant to the query, in document order
+    pub fn search(&self, query: &str, k: usize) -> Vec<usize> {
+        let mut query_terms = terms(query);
+        query_terms.sort();
+        query_terms.dedup();
+
+        let n = self.chunks.len() as f32;
+        let mut scores = self
+            .terms
+            .iter()
+            .enumerate()
+            .map(|(i, freqs)| {
+                let length_norm = 1.0 - B + B * self.lengths[i] as f32 / self.avg_length.max(1.0);
+                let score = query_terms
+                    .iter()
+                    .filter_map(|term| {
+                        let tf = *freqs.get(term)? as f32;
+                        let df = self.chunk_freqs[term] as f32;
+                        let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                        Some(idf * tf * (K1 + 1.0) / (tf + K1 * length_norm))
+                    })
+                    .sum::<f32>();
+                (i, score)
+            })
+            .filter(|(_, score)| *score > 0.0)
+            .collect::<Vec<(usize, f32)>>();
+
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut top = scores
+            .into_iter()
+            .take(k)
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        top.sort();
+        top
+    }
+}
+
+// Split text into chunks of about the given number of tokens. Chunks are
+// made of whole paragraphs where possible, long paragraphs are split
+// into sentences. Chunks of a PDF start with the marker of the page they
+// are on, so excerpts of the document can still be cited by page.
+pub fn chunk(text: &str, max_tokens: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut page: Option<String> = None;
+
+    for piece in text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .flat_map(|paragraph| split_long(paragraph, max_tokens))
+    {
+        let tokens = crate::tokens::estimate(&current) + crate::tokens::estimate(&piece);
+        if !current.is_empty() && tokens > max_tokens {
+            chunks.push(std::mem::take(&mut current));
+        }
+
+        let is_page_marker = crate::pdf::is_page_marker(&piece);
+        if current.is_empty() && !is_page_marker {
+            if let Some(page) = &page {
+                current.push_str(page);
+            }
+        }
+        if is_page_marker {
+            page = Some(piece.clone());
+        }
+
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(&piece);
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+// Beginning of the text that fits into the given number of tokens. The text is
+// cut at the end of a chunk, so the excerpt doesn't end in the middle of a sentence.
+pub fn excerpt(text: &str, max_tokens: usize) -> String {
+    if crate::tokens::estimate(text) <= max_tokens {
+        return text.to_string();
+    }
+
+    let mut excerpt = String::new();
+    let mut tokens = 0;
+    for chunk in chunk(text, CHUNK_TOKENS.min(max_tokens)) {
+        tokens += crate::tokens::estimate(&chunk);
+        if tokens > max_tokens {
+            break;
+        }
+
+        if !excerpt.is_empty() {
+            excerpt.push('\n');
+        }
+        excerpt.push_str(&chunk);
+    }
+
+    excerpt
+}
+
+// Split a paragraph that is too long into sentences, and
+// sentences that are still too long at word boundaries
+fn split_long(paragraph: &str, max_tokens: usize) -> Vec<String> {
+    if crate::tokens::estimate(paragraph) <= max_tokens {
+        return vec![paragraph.to_string()];
+    }
+
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0;
+    for word in paragraph.split_inclusive(char::is_whitespace) {
+        let word_tokens = crate::tokens::estimate(word);
+        if !current.is_empty() && current_tokens + word_tokens > max_tokens {
+            pieces.push(current.trim().to_string());
+            current.clear();
+            current_tokens = 0;
+        }
+
+        current.push_str(word);
+        current_tokens += word_tokens;
+
+        // prefer to end pieces at the end of a sentence
+        let sentence_end = current
+            .trim_end()
+            .ends_with(['.', '?', '!', '。', '？', '！']);
+        if sentence_end && current_tokens > max_tokens / 2 {
+            pieces.push(current.trim().to_string());
+            current.clear();
+            current_tokens = 0;
+        }
+    }
+
+    if !current.trim().is_empty() {
+        pieces.push(current.trim().to_string());
+    }
+
+    pieces
+}
+
+// Lowercase terms of the text. Scripts that don't separate words
+// by spaces, like Chinese and Japanese, are indexed by character.
+pub fn terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if is_cjk(c) {
+            if !current.is_empty() {
+                terms.push(std::mem::take(&mut current));
+            }
+            terms.push(c.to_string());
+        } else if c.is_alphanumeric() {
+            current.push(c);
+        } else if !current.is_empty() {
+            terms.push(std::mem::take(&mut current));
+        }
+    }
+
+    if !current.is_empty() {
+        terms.push(current);
+    }
+
+    terms
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // hiragana and katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK unified ideographs
+        | '\u{f900}'..='\u{faff}' // CJK compatibility ideographs
+    )
+}
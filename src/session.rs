@@ -1,9 +1,11 @@
-use arraydeque::{ArrayDeque, Wrapping};
+use crate::embedding::VectorIndex;
+use crate::transcript::Transcript;
 use lru::LruCache;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::num::NonZeroUsize;
-use std::sync::{LazyLock, Mutex};
+use std::sync::{Arc, LazyLock, Mutex, OnceLock, Weak};
 
-// Maximum number of concurrently stored sessions
+// Default maximum number of concurrently stored sessions
 // This is the maximum number of conversations that
 // we keep track of in memory. If the number of
 // sessions exceeds this limit, the least recently
@@ -11,13 +13,35 @@ use std::sync::{LazyLock, Mutex};
 // the new session.
 const MAX_SESSIONS: usize = 100;
 
-// The length of the context window for each session
+// Default length of the context window for each session
 // This is the number of messages that we keep track of
 // in a conversation. If a session reaches this limit,
 // the oldest message will be dropped to make room for
 // the new message.
 const MAX_MESSAGES_PER_SESSION: usize = 100;
 
+// Default maximum memory used by all sessions together. Memory use mostly
+// depends on the length of the documents, so we limit the bytes as well.
+const MAX_STORE_BYTES: usize = 64 * 1024 * 1024;
+
+// Share of the token budget a session may use before older
+// messages get compacted into a recap of the conversation
+const COMPACTION_THRESHOLD: f32 = 0.8;
+
+// Number of chunks of the document we send along with a follow-up
+// question, unless the session is in full document mode
+const RETRIEVAL_TOP_K: usize = 5;
+
+// Time after which an unused session expires, in milliseconds
+const DEFAULT_SESSION_TTL: u64 = 2 * 60 * 60 * 1000;
+
+// Number of expired session ids we remember, to tell
+// expired sessions apart from ones that never existed
+const MAX_EXPIRED_IDS: usize = 100;
+
+// Version of the JSON format of a saved store, bump on incompatible changes
+const STORE_FORMAT_VERSION: u64 = 2;
+
 // This is our global storage for chat sessions.
 // We use a lazy lock to ensure that the store is only initialized once.
 pub static STORE: LazyLock<SessionStore> = LazyLock::new(SessionStore::new);
@@ -25,6 +49,15 @@ pub static STORE: LazyLock<SessionStore> = LazyLock::new(SessionStore::new);
 // Session store to keep track of the context of a conversation
 pub struct SessionStore {
     sessions: Mutex<LruCache<String, Session>>,
+
+    // Ids of sessions that were removed because they expired
+    expired: Mutex<LruCache<String, ()>>,
+
+    // Documents by the hash of their text, shared by all sessions about the
+    // same text. A document is dropped with the last session that refers to it.
+    documents: Mutex<HashMap<String, Weak<Document>>>,
+
+    config: Mutex<StoreConfig>,
 }
 
 impl SessionStore {
@@ -32,7 +65,147 @@ impl SessionStore {
     fn new() -> Self {
         Self {
             sessions: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_SESSIONS).unwrap())),
+            expired: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_EXPIRED_IDS).unwrap())),
+            documents: Mutex::new(HashMap::new()),
+            config: Mutex::new(StoreConfig::default()),
+        }
+    }
+
+    pub fn config(&self) -> StoreConfig {
+        *self.config.lock().unwrap()
+    }
+
+    // Change the limits of the store. Sessions and messages beyond
+    // the new limits are dropped, least recently used first.
+    pub fn configure(&self, config: StoreConfig) {
+        *self.config.lock().unwrap() = config;
+
+        let mut guard = self.sessions.lock().unwrap();
+        guard.resize(NonZeroUsize::new(config.max_sessions.max(1)).unwrap());
+        for (_, session) in guard.iter_mut() {
+            session.truncate(config.max_messages_per_session);
+        }
+        self.enforce_byte_limit(&mut guard);
+    }
+
+    // Set the time after which an unused session expires, in milliseconds
+    pub fn set_ttl(&self, ttl: u64) {
+        self.config.lock().unwrap().ttl = ttl;
+    }
+
+    // Number of sessions and messages and the approximate memory they use
+    pub fn stats(&self) -> StoreStats {
+        let guard = self.sessions.lock().unwrap();
+        StoreStats {
+            sessions: guard.len(),
+            messages: guard
+                .iter()
+                .map(|(_, session)| session.message_count())
+                .sum(),
+            documents: guard
+                .iter()
+                .filter_map(|(_, session)| session.document.as_ref().map(Arc::as_ptr))
+                .collect::<HashSet<*const Document>>()
+                .len(),
+            bytes: store_bytes(&guard),
+        }
+    }
+
+    // Get the document with the given text. If another session is about the
+    // same text its document is shared, otherwise the text is indexed.
+    fn document(&self, text: &str) -> Arc<Document> {
+        let hash = crate::util::content_hash(text);
+        let mut documents = self.documents.lock().unwrap();
+        documents.retain(|_, document| document.strong_count() > 0);
+
+        if let Some(document) = documents.get(&hash).and_then(Weak::upgrade) {
+            // different texts might have the same hash
+            if document.text == text {
+                return document;
+            }
+        }
+
+        let document = Arc::new(Document::new(&hash, text));
+        documents.insert(hash, Arc::downgrade(&document));
+        document
+    }
+
+    // Get the document saved with `Document::to_json`, shared like in `document`.
+    // Embeddings aren't saved, the document is embedded again when needed.
+    fn restore_document(&self, value: &serde_json::Value) -> Result<Arc<Document>, anyhow::Error> {
+        let text = value["text"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing document text"))?;
+
+        Ok(self.document(text))
+    }
+
+    // Evict the least recently used sessions until all sessions fit into the
+    // byte limit. The most recently used session is kept, even if it's too big.
+    fn enforce_byte_limit(&self, sessions: &mut LruCache<String, Session>) {
+        let max_bytes = self.config.lock().unwrap().max_bytes;
+        while sessions.len() > 1 && store_bytes(sessions) > max_bytes {
+            sessions.pop_lru();
+        }
+    }
+
+    // Whether the session with the given id was removed because it expired
+    pub fn is_expired(&self, id: &str) -> bool {
+        self.expired.lock().unwrap().contains(id)
+    }
+
+    // Remove all expired sessions, returns the number of removed sessions
+    pub fn sweep_expired(&self) -> usize {
+        let now = crate::util::now();
+        let ttl = self.config.lock().unwrap().ttl;
+
+        let mut guard = self.sessions.lock().unwrap();
+        let ids = guard
+            .iter()
+            .filter(|(_, session)| session.is_expired(now, ttl))
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<String>>();
+
+        for id in &ids {
+            guard.pop(id);
+            self.expired.lock().unwrap().put(id.clone(), ());
+        }
+
+        ids.len()
+    }
+
+    // Get a session that hasn't expired and mark it as used. An expired
+    // session is removed, and its id remembered for `is_expired`.
+    fn active<'a>(
+        &self,
+        sessions: &'a mut LruCache<String, Session>,
+        id: &str,
+    ) -> Option<&'a mut Session> {
+        let now = crate::util::now();
+        let ttl = self.config.lock().unwrap().ttl;
+
+        if sessions.peek(id)?.is_expired(now, ttl) {
+            sessions.pop(id);
+            self.expired.lock().unwrap().put(id.to_string(), ());
+            return None;
         }
+
+        let session = sessions.get_mut(id)?;
+        session.last_used = now;
+        Some(session)
+    }
+
+    // Look at a session that hasn't expired without marking it as used
+    fn peek_active<'a>(
+        &self,
+        sessions: &'a LruCache<String, Session>,
+        id: &str,
+    ) -> Option<&'a Session> {
+        let now = crate::util::now();
+        let ttl = self.config.lock().unwrap().ttl;
+        sessions
+            .peek(id)
+            .filter(|session| !session.is_expired(now, ttl))
     }
 
     // Create a new session in the store
@@ -40,22 +213,215 @@ impl SessionStore {
         let session = Session::new(prompts);
         let mut guard = self.sessions.lock().unwrap();
         guard.push(id.to_string(), session);
+        self.expired.lock().unwrap().pop(id);
+        self.enforce_byte_limit(&mut guard);
+    }
+
+    // Get the context window for a given session, limited to the given number of tokens.
+    // If a query is given, only the parts of the document relevant to the query are
+    // included. Prompts, summary and recap are always included, chat messages are added
+    // from newest to oldest for as long as they fit.
+    pub fn context_window_within(
+        &self,
+        id: &str,
+        query: Option<Query>,
+        budget: usize,
+    ) -> Option<Vec<Message>> {
+        let mut guard = self.sessions.lock().unwrap();
+        self.active(&mut guard, id)
+            .map(|session| session.context_window_within(query.as_ref(), budget))
+    }
+
+    // Set the document a given session is about, the document is indexed for
+    // retrieval unless another session is about the same text already
+    pub fn set_document(&self, id: &str, text: &str) {
+        let document = self.document(text);
+        let mut guard = self.sessions.lock().unwrap();
+        if let Some(session) = self.active(&mut guard, id) {
+            session.document = Some(document);
+            session.updated_at = session.last_used;
+        }
+        self.enforce_byte_limit(&mut guard);
+    }
+
+    // Get the text of the document of a given session
+    pub fn document_text(&self, id: &str) -> Option<String> {
+        let mut guard = self.sessions.lock().unwrap();
+        self.active(&mut guard, id)
+            .and_then(|session| session.document.as_ref())
+            .map(|document| document.text.clone())
+    }
+
+    // Get the chunks of the document of a given session that still need embeddings
+    pub fn unembedded_chunks(&self, id: &str) -> Option<Vec<String>> {
+        let mut guard = self.sessions.lock().unwrap();
+        self.active(&mut guard, id)
+            .and_then(|session| session.document.as_ref())
+            .filter(|document| document.embeddings.get().is_none())
+            .map(|document| document.index.chunks().to_vec())
+    }
+
+    // Whether the document of a given session has embeddings
+    pub fn has_embeddings(&self, id: &str) -> bool {
+        let mut guard = self.sessions.lock().unwrap();
+        self.active(&mut guard, id)
+            .and_then(|session| session.document.as_ref())
+            .is_some_and(|document| document.embeddings.get().is_some())
+    }
+
+    // Set the embeddings of the document chunks of a given session. The
+    // embeddings are shared with all sessions about the same document.
+    pub fn set_embeddings(&self, id: &str, embeddings: VectorIndex) {
+        let mut guard = self.sessions.lock().unwrap();
+        if let Some(document) = self
+            .active(&mut guard, id)
+            .and_then(|s| s.document.as_ref())
+        {
+            // the document might have been replaced while we were embedding
+            if embeddings.len() == document.index.chunks().len() {
+                let _ = document.embeddings.set(embeddings);
+            }
+        }
+        self.enforce_byte_limit(&mut guard);
+    }
+
+    // Always send the full document instead of the relevant parts for a given session
+    pub fn set_full_document(&self, id: &str, enabled: bool) {
+        let mut guard = self.sessions.lock().unwrap();
+        if let Some(session) = self.active(&mut guard, id) {
+            session.full_document = enabled;
+        }
     }
 
-    // Get the context window for a given session
-    pub fn context_window(&self, id: &str) -> Option<Vec<Message>> {
+    // Get the messages of a given session that should be compacted into a recap
+    // to keep the session within the given number of tokens, if any
+    pub fn compaction(&self, id: &str, budget: usize) -> Option<Compaction> {
         let mut guard = self.sessions.lock().unwrap();
-        guard.get(id).map(|session| session.context_window())
+        self.active(&mut guard, id)
+            .and_then(|session| session.compaction(budget))
+    }
+
+    // Replace the compacted messages of a given session with the recap
+    pub fn compact(&self, id: &str, compaction: &Compaction, recap: &str) {
+        let mut guard = self.sessions.lock().unwrap();
+        if let Some(session) = self.active(&mut guard, id) {
+            session.compact(compaction, recap);
+            session.updated_at = session.last_used;
+        }
+    }
+
+    // Record the summary the user was shown for a given session
+    pub fn set_summary(&self, id: &str, summary: Summary) {
+        let mut guard = self.sessions.lock().unwrap();
+        if let Some(session) = self.active(&mut guard, id) {
+            session.summary = Some(summary);
+            session.updated_at = session.last_used;
+        }
     }
 
     // Append messages to the context window of a given session
     pub fn append_messages(&self, id: &str, messages: Vec<Message>) {
+        let max_messages = self.config.lock().unwrap().max_messages_per_session;
         let mut guard = self.sessions.lock().unwrap();
-        if let Some(session) = guard.get_mut(id) {
+        if let Some(session) = self.active(&mut guard, id) {
             for message in messages {
-                session.append(message);
+                session.append(message, max_messages);
             }
+            session.updated_at = session.last_used;
         }
+        self.enforce_byte_limit(&mut guard);
+    }
+
+    // Get the chat messages of a given session, oldest first
+    pub fn messages(&self, id: &str) -> Option<Vec<Message>> {
+        let guard = self.sessions.lock().unwrap();
+        self.peek_active(&guard, id)
+            .map(|session| session.chat.iter().cloned().collect())
+    }
+
+    // Remove the message with the given id and all later messages from the chat of
+    // a given session, to ask again from there. Returns the removed messages, or
+    // None if the message isn't in the chat anymore.
+    pub fn truncate_from(&self, id: &str, message_id: u64) -> Option<Vec<Message>> {
+        let mut guard = self.sessions.lock().unwrap();
+        let session = self.active(&mut guard, id)?;
+        let removed = session.truncate_from(message_id)?;
+        session.updated_at = session.last_used;
+        Some(removed)
+    }
+
+    // Remove a question and its answer from the chat of a given session, given the
+    // id of either. Returns false if the session or message doesn't exist.
+    pub fn delete_turn(&self, id: &str, message_id: u64) -> bool {
+        let mut guard = self.sessions.lock().unwrap();
+        let Some(session) = self.active(&mut guard, id) else {
+            return false;
+        };
+
+        let deleted = session.delete_turn(message_id);
+        if deleted {
+            session.updated_at = session.last_used;
+        }
+        deleted
+    }
+
+    // Copy a session into a new session to explore another line of questioning. The
+    // chat is copied up to and including the given message, or in full without one.
+    // Returns false if the session or the message doesn't exist.
+    pub fn fork(&self, id: &str, new_id: &str, at_message: Option<u64>) -> bool {
+        let mut guard = self.sessions.lock().unwrap();
+        let Some(fork) = self
+            .active(&mut guard, id)
+            .and_then(|session| session.fork(at_message))
+        else {
+            return false;
+        };
+
+        guard.push(new_id.to_string(), fork);
+        self.expired.lock().unwrap().pop(new_id);
+        self.enforce_byte_limit(&mut guard);
+        true
+    }
+
+    // Record the title of the document of a given session
+    pub fn set_title(&self, id: &str, title: Option<&str>) {
+        let mut guard = self.sessions.lock().unwrap();
+        if let Some(session) = self.active(&mut guard, id) {
+            session.title = title.map(str::to_string);
+        }
+    }
+
+    // Record the model that answered last in a given session
+    pub fn set_model(&self, id: &str, model: &str) {
+        let mut guard = self.sessions.lock().unwrap();
+        if let Some(session) = self.active(&mut guard, id) {
+            session.model = Some(model.to_string());
+        }
+    }
+
+    // Get information about a given session, without marking it as used
+    pub fn info(&self, id: &str) -> Option<SessionInfo> {
+        let guard = self.sessions.lock().unwrap();
+        self.peek_active(&guard, id).map(|session| session.info(id))
+    }
+
+    // Get information about all sessions, most recently used first
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let now = crate::util::now();
+        let ttl = self.config.lock().unwrap().ttl;
+
+        let guard = self.sessions.lock().unwrap();
+        guard
+            .iter()
+            .filter(|(_, session)| !session.is_expired(now, ttl))
+            .map(|(id, session)| session.info(id))
+            .collect()
+    }
+
+    // Get the summary and conversation of a given session
+    pub fn transcript(&self, id: &str) -> Option<Transcript> {
+        let guard = self.sessions.lock().unwrap();
+        self.peek_active(&guard, id).map(Session::transcript)
     }
 
     // Remove a session from the store
@@ -63,50 +429,861 @@ impl SessionStore {
         let mut guard = self.sessions.lock().unwrap();
         guard.pop(&id.to_string());
     }
+
+    // Serialize the most recently used sessions whose JSON fits into the given
+    // number of bytes, the least recently used sessions are dropped first. The
+    // sessions are ordered from least to most recently used. Documents are
+    // serialized once and referred to by their hash from the sessions.
+    pub fn to_json_within(&self, max_bytes: usize) -> serde_json::Value {
+        let guard = self.sessions.lock().unwrap();
+
+        let mut documents = serde_json::Map::new();
+        let mut sessions = Vec::new();
+        let mut bytes = 0;
+        for (id, session) in guard.iter() {
+            let mut value = session.to_json();
+            value["id"] = serde_json::Value::from(id.as_str());
+            let mut size = value.to_string().len();
+
+            let document = session
+                .document
+                .as_ref()
+                .filter(|document| !documents.contains_key(&document.hash))
+                .map(|document| (document.hash.clone(), document.to_json()));
+            if let Some((_, document)) = &document {
+                size += document.to_string().len();
+            }
+
+            if bytes + size > max_bytes {
+                break;
+            }
+            bytes += size;
+            if let Some((hash, document)) = document {
+                documents.insert(hash, document);
+            }
+            sessions.push(value);
+        }
+        sessions.reverse();
+
+        serde_json::json!({
+            "version": STORE_FORMAT_VERSION,
+            "documents": documents,
+            "sessions": sessions,
+        })
+    }
+
+    // Restore sessions serialized with `to_json_within`. Sessions that already exist in
+    // the store are kept and stay the most recently used ones. Returns the number
+    // of restored sessions.
+    pub fn restore(&self, json: &str) -> Result<usize, anyhow::Error> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+
+        let version = value["version"].as_u64().unwrap_or_default();
+        if version != STORE_FORMAT_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported store format version {}",
+                version
+            ));
+        }
+
+        let mut documents = HashMap::new();
+        if let Some(saved) = value["documents"].as_object() {
+            for (hash, document) in saved {
+                documents.insert(hash.clone(), self.restore_document(document)?);
+            }
+        }
+
+        let max_messages = self.config.lock().unwrap().max_messages_per_session;
+        let sessions = value["sessions"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("Missing sessions"))?
+            .iter()
+            .map(|session| {
+                let id = session["id"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing session id"))?;
+                let session = Session::from_json(session, max_messages, &documents)?;
+                Ok((id.to_string(), session))
+            })
+            .collect::<Result<Vec<(String, Session)>, anyhow::Error>>()?;
+
+        let mut guard = self.sessions.lock().unwrap();
+        let existing = guard
+            .iter()
+            .rev()
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<String>>();
+
+        let mut restored = 0;
+        for (id, session) in sessions {
+            if !guard.contains(&id) {
+                guard.push(id, session);
+                restored += 1;
+            }
+        }
+
+        for id in existing {
+            guard.promote(&id);
+        }
+        self.enforce_byte_limit(&mut guard);
+
+        Ok(restored)
+    }
 }
 
 // Session struct to store the context of a conversation
 struct Session {
+    // When the session was created, last changed and last
+    // used, in milliseconds since the Unix epoch
+    created_at: u64,
+    updated_at: u64,
+    last_used: u64,
+
+    // Title of the document and the model that answered last
+    title: Option<String>,
+    model: Option<String>,
+
+    // The document this session is about, if any. Sessions about the
+    // same text, like forks, share the document instead of copying it.
+    document: Option<Arc<Document>>,
+
+    // Whether to send the full document with every request
+    // instead of the parts relevant to the question
+    full_document: bool,
+
     // The initial prompts for this session
     prompts: Vec<Message>,
 
+    // The summary, including the suggested questions and answers,
+    // that the user was shown for the text of this session
+    summary: Option<Summary>,
+
+    // Running recap of older messages that were compacted
+    recap: Option<String>,
+
+    // Id of the next message added to the chat
+    next_id: u64,
+
+    // Messages that were pushed out of the chat
+    // and still need to be compacted into the recap
+    evicted: Vec<Message>,
+
     // The context window of the conversation about the text
-    // We are using a ring buffer to store the last
-    // MAX_MESSAGES_PER_SESSION messages
-    chat: ArrayDeque<Message, MAX_MESSAGES_PER_SESSION, Wrapping>,
+    // We keep the last `max_messages_per_session` messages
+    chat: VecDeque<Message>,
 }
 
 impl Session {
     // Create a new session with the given prompts
     fn new(prompts: Vec<Message>) -> Self {
+        let now = crate::util::now();
         Self {
+            created_at: now,
+            updated_at: now,
+            last_used: now,
+            title: None,
+            model: None,
+            document: None,
+            full_document: false,
             prompts,
-            chat: ArrayDeque::new(),
+            summary: None,
+            recap: None,
+            next_id: 1,
+            evicted: Vec::new(),
+            chat: VecDeque::new(),
         }
     }
 
+    // Whether the session hasn't been used for longer than the given ttl
+    fn is_expired(&self, now: u64, ttl: u64) -> bool {
+        now.saturating_sub(self.last_used) > ttl
+    }
+
     // Append a new message to the context window
-    // If the buffer is full, the oldest message will be
-    // dropped to make room for the new message
-    fn append(&mut self, message: Message) {
+    // If the chat is full, the oldest message will be
+    // moved out of the chat to be compacted later
+    fn append(&mut self, mut message: Message, max_messages: usize) {
+        if message.id == 0 {
+            message.id = self.next_id;
+        }
+        self.next_id = self.next_id.max(message.id + 1);
+
         self.chat.push_back(message);
+        self.truncate(max_messages);
+    }
+
+    // Remove the message with the given id and all messages after it from the
+    // chat. Returns the removed messages, or None if the message isn't in the chat.
+    fn truncate_from(&mut self, message_id: u64) -> Option<Vec<Message>> {
+        let position = self.chat.iter().position(|m| m.id == message_id)?;
+        Some(self.chat.split_off(position).into())
+    }
+
+    // Remove the question with the given id and its answer, or the answer
+    // with the given id and its question. Returns false if there is no such turn.
+    fn delete_turn(&mut self, message_id: u64) -> bool {
+        let Some(position) = self.chat.iter().position(|m| m.id == message_id) else {
+            return false;
+        };
+
+        let start = match self.chat[position].source {
+            MessageSource::Assistant if position > 0 => match self.chat[position - 1].source {
+                MessageSource::User => position - 1,
+                _ => position,
+            },
+            _ => position,
+        };
+
+        // a question is followed by its answers until the next question
+        let end = self
+            .chat
+            .iter()
+            .skip(start + 1)
+            .position(|m| m.source == MessageSource::User)
+            .map(|offset| start + 1 + offset)
+            .unwrap_or(self.chat.len());
+
+        self.chat.drain(start..end);
+        true
+    }
+
+    // Move the oldest messages out of the chat until it has at most the given length
+    fn truncate(&mut self, max_messages: usize) {
+        while self.chat.len() > max_messages {
+            if let Some(evicted) = self.chat.pop_front() {
+                self.evicted.push(evicted);
+            }
+        }
+    }
+
+    // Copy of this session with the chat up to and including the given message
+    fn fork(&self, at_message: Option<u64>) -> Option<Session> {
+        let end = match at_message {
+            Some(message_id) => self.chat.iter().position(|m| m.id == message_id)? + 1,
+            None => self.chat.len(),
+        };
+
+        let now = crate::util::now();
+        Some(Session {
+            created_at: now,
+            updated_at: now,
+            last_used: now,
+            title: self.title.clone(),
+            model: self.model.clone(),
+            document: self.document.clone(),
+            full_document: self.full_document,
+            prompts: self.prompts.clone(),
+            summary: self.summary.clone(),
+            recap: self.recap.clone(),
+            next_id: self.next_id,
+            evicted: self.evicted.clone(),
+            chat: self.chat.iter().take(end).cloned().collect(),
+        })
     }
 
-    // Get the context window for this session
-    fn context_window(&self) -> Vec<Message> {
+    fn info(&self, id: &str) -> SessionInfo {
+        SessionInfo {
+            id: id.to_string(),
+            title: self.title.clone(),
+            model: self.model.clone(),
+            language: self
+                .summary
+                .as_ref()
+                .map(|summary| summary.language.clone()),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            messages: self.evicted.len() + self.chat.len(),
+        }
+    }
+
+    fn transcript(&self) -> Transcript {
+        Transcript {
+            title: self.title.clone(),
+            model: self.model.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            summary: self.summary.clone(),
+            recap: self.recap.clone(),
+            messages: self
+                .evicted
+                .iter()
+                .chain(&self.chat)
+                .filter(|message| message.source != MessageSource::System)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    // Number of stored messages, including prompts and evicted messages
+    fn message_count(&self) -> usize {
+        self.prompts.len() + self.evicted.len() + self.chat.len()
+    }
+
+    // Approximate memory used by this session in bytes, without
+    // the document, which may be shared with other sessions
+    fn bytes(&self) -> usize {
+        let messages = self
+            .prompts
+            .iter()
+            .chain(&self.evicted)
+            .chain(&self.chat)
+            .map(|message| message.text.len())
+            .sum::<usize>();
+
+        messages
+            + self
+                .summary
+                .as_ref()
+                .map(Summary::bytes)
+                .unwrap_or_default()
+            + self.recap.as_ref().map(String::len).unwrap_or_default()
+    }
+
+    // Get the context window for this session limited to the given number of tokens
+    fn context_window_within(&self, query: Option<&Query>, budget: usize) -> Vec<Message> {
+        let mut context = self.fixed_context(query);
+        let mut remaining = budget.saturating_sub(tokens(&context));
+
+        // add the newest messages that fit into the remaining budget
+        let mut recent = Vec::new();
+        for message in self.chat.iter().rev() {
+            if message.tokens() > remaining {
+                break;
+            }
+            remaining -= message.tokens();
+            recent.push(message.clone());
+        }
+
+        context.extend(recent.into_iter().rev());
+        context
+    }
+
+    // The part of the context window that doesn't depend on the chat
+    fn fixed_context(&self, query: Option<&Query>) -> Vec<Message> {
+        let mut context = Vec::new();
+        if let Some(document) = self.document_message(query) {
+            context.push(document);
+        }
+
+        context.extend(self.prompt_context());
+        context
+    }
+
+    // Prompts, summary and recap of this session
+    fn prompt_context(&self) -> Vec<Message> {
         // always prepend the original prompts to the context
         // window, we do this here instead adding them directly
         // to the beginning of `self.chat` to avoid losing them
-        // when the ring buffer is full
+        // when the chat is full
         let mut context = self.prompts.clone();
 
-        // add the messages from the ring buffer
-        context.extend(self.chat.iter().cloned().collect::<Vec<Message>>());
+        // let the model know what the user has already read, so
+        // follow-up questions can refer to the summary and answers
+        if let Some(summary) = &self.summary {
+            context.push(summary.message());
+        }
+
+        if let Some(recap) = &self.recap {
+            context.push(Message::system(&format!(
+                "Recap of the earlier conversation with the user, \
+                the original messages are no longer available:\n{}",
+                recap
+            )));
+        }
 
-        // return the context window
         context
     }
+
+    // Whether we only send the parts of the document relevant to a question
+    fn uses_retrieval(&self) -> bool {
+        match &self.document {
+            Some(document) => {
+                !self.full_document && document.index.chunks().len() > RETRIEVAL_TOP_K
+            }
+            None => false,
+        }
+    }
+
+    // The document as a user message. If there is a query and the document is
+    // long, the message only contains the chunks most relevant to the query.
+    fn document_message(&self, query: Option<&Query>) -> Option<Message> {
+        let document = self.document.as_ref()?;
+
+        let query = match query {
+            Some(query) if self.uses_retrieval() => query,
+            _ => return Some(Message::user(&document.text)),
+        };
+
+        let chunks = document.index.chunks();
+        let mut text = String::from(
+            "The text is too long to share in full. \
+            These are the excerpts most relevant to the question:\n",
+        );
+
+        // questions like "what is this about?" may not match any chunk,
+        // the beginning of the document is the best guess then
+        let mut relevant = match (&query.embedding, document.embeddings.get()) {
+            (Some(embedding), Some(embeddings)) => embeddings.search(embedding, RETRIEVAL_TOP_K),
+            _ => document.index.search(query.text, RETRIEVAL_TOP_K),
+        };
+        if relevant.is_empty() {
+            relevant = (0..RETRIEVAL_TOP_K).collect();
+        }
+
+        for i in relevant {
+            text.push_str(&format!(
+                "\n[Excerpt {} of {}]\n{}\n",
+                i + 1,
+                chunks.len(),
+                chunks[i]
+            ));
+        }
+
+        Some(Message::user(&text))
+    }
+
+    // Maximum number of tokens the document takes up in the context window
+    fn document_tokens(&self) -> usize {
+        let Some(document) = &self.document else {
+            return 0;
+        };
+
+        if !self.uses_retrieval() {
+            return crate::tokens::estimate(&document.text);
+        }
+
+        let mut chunk_tokens = document
+            .index
+            .chunks()
+            .iter()
+            .map(|chunk| crate::tokens::estimate(chunk))
+            .collect::<Vec<usize>>();
+        chunk_tokens.sort_by(|a, b| b.cmp(a));
+        chunk_tokens.iter().take(RETRIEVAL_TOP_K).sum()
+    }
+
+    // Messages to compact if the session gets close to the given budget. We keep
+    // the newest messages that fit into half of the budget left for the chat.
+    fn compaction(&self, budget: usize) -> Option<Compaction> {
+        let fixed = self.document_tokens() + tokens(&self.prompt_context());
+        let chat = self.chat.iter().map(Message::tokens).sum::<usize>();
+
+        let threshold = (budget as f32 * COMPACTION_THRESHOLD) as usize;
+        if self.evicted.is_empty() && fixed + chat <= threshold {
+            return None;
+        }
+
+        let keep_budget = budget.saturating_sub(fixed) / 2;
+        let mut kept_tokens = 0;
+        let mut keep = 0;
+        for message in self.chat.iter().rev() {
+            if kept_tokens + message.tokens() > keep_budget {
+                break;
+            }
+            kept_tokens += message.tokens();
+            keep += 1;
+        }
+
+        let chat_count = self.chat.len() - keep;
+        if self.evicted.is_empty() && chat_count == 0 {
+            return None;
+        }
+
+        let mut messages = self.evicted.clone();
+        messages.extend(self.chat.iter().take(chat_count).cloned());
+
+        Some(Compaction {
+            recap: self.recap.clone(),
+            messages,
+            evicted: self.evicted.len(),
+            chat: chat_count,
+        })
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "created_at": self.created_at,
+            "updated_at": self.updated_at,
+            "last_used": self.last_used,
+            "title": self.title,
+            "model": self.model,
+            "document": self.document.as_ref().map(|document| &document.hash),
+            "full_document": self.full_document,
+            "prompts": self.prompts.iter().map(Message::to_json).collect::<Vec<_>>(),
+            "summary": self.summary.as_ref().map(Summary::to_json),
+            "recap": self.recap,
+            "evicted": self.evicted.iter().map(Message::to_json).collect::<Vec<_>>(),
+            "chat": self.chat.iter().map(Message::to_json).collect::<Vec<_>>(),
+        })
+    }
+
+    // Deserialize a session, its document is looked up by hash in the given documents
+    fn from_json(
+        value: &serde_json::Value,
+        max_messages: usize,
+        documents: &HashMap<String, Arc<Document>>,
+    ) -> Result<Self, anyhow::Error> {
+        let messages = |name: &str| {
+            value[name]
+                .as_array()
+                .map(|items| items.iter().map(Message::from_json).collect())
+                .unwrap_or_else(|| Ok(Vec::new()))
+        };
+
+        let mut session = Self::new(messages("prompts")?);
+        if let Some(created_at) = value["created_at"].as_u64() {
+            session.created_at = created_at;
+        }
+        if let Some(last_used) = value["last_used"].as_u64() {
+            session.last_used = last_used;
+        }
+        session.updated_at = value["updated_at"].as_u64().unwrap_or(session.last_used);
+        session.title = value["title"].as_str().map(str::to_string);
+        session.model = value["model"].as_str().map(str::to_string);
+        session.document = match &value["document"] {
+            serde_json::Value::Null => None,
+            hash => Some(
+                hash.as_str()
+                    .and_then(|hash| documents.get(hash))
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown document: {}", hash))?,
+            ),
+        };
+        session.full_document = value["full_document"].as_bool().unwrap_or_default();
+        session.summary = match &value["summary"] {
+            serde_json::Value::Null => None,
+            summary => Summary::from_json(
+                summary["language"].as_str().unwrap_or_default(),
+                &summary.to_string(),
+            ),
+        };
+        session.recap = value["recap"].as_str().map(str::to_string);
+        session.evicted = messages("evicted")?;
+        session.next_id = session.evicted.iter().map(|m| m.id + 1).max().unwrap_or(1);
+        for message in messages("chat")? {
+            session.append(message, max_messages);
+        }
+
+        Ok(session)
+    }
+
+    // Drop the compacted messages and keep the recap instead
+    fn compact(&mut self, compaction: &Compaction, recap: &str) {
+        // messages might have been pushed out of the chat since the
+        // compaction was computed, those were part of the compacted chat
+        let shifted = self
+            .evicted
+            .len()
+            .saturating_sub(compaction.evicted)
+            .min(compaction.chat);
+
+        let evicted = (compaction.evicted + shifted).min(self.evicted.len());
+        self.evicted.drain(..evicted);
+
+        for _ in 0..compaction.chat - shifted {
+            self.chat.pop_front();
+        }
+
+        self.recap = Some(recap.to_string());
+    }
+}
+
+// Limits of the session store
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StoreConfig {
+    pub max_sessions: usize,
+    pub max_messages_per_session: usize,
+
+    // Approximate memory all sessions may use together
+    pub max_bytes: usize,
+
+    // Time after which an unused session expires, in milliseconds
+    pub ttl: u64,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            max_sessions: MAX_SESSIONS,
+            max_messages_per_session: MAX_MESSAGES_PER_SESSION,
+            max_bytes: MAX_STORE_BYTES,
+            ttl: DEFAULT_SESSION_TTL,
+        }
+    }
+}
+
+impl StoreConfig {
+    // Update the limits given in the JSON object, e.g. {"max_sessions": 20}.
+    // The ttl is given in minutes as "ttl_minutes".
+    pub fn merge_json(mut self, json: &str) -> Result<Self, anyhow::Error> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        if !value.is_object() {
+            return Err(anyhow::anyhow!("Expected a JSON object, got {}", value));
+        }
+
+        // limits too large for the platform are as good as no limit
+        let size = |limit: u64| usize::try_from(limit).unwrap_or(usize::MAX);
+        let limit = |name: &str| -> Result<Option<u64>, anyhow::Error> {
+            match &value[name] {
+                serde_json::Value::Null => Ok(None),
+                limit => match limit.as_u64() {
+                    Some(limit) if limit > 0 => Ok(Some(limit)),
+                    _ => Err(anyhow::anyhow!("Invalid {}: {}", name, limit)),
+                },
+            }
+        };
+
+        if let Some(max_sessions) = limit("max_sessions")? {
+            self.max_sessions = size(max_sessions);
+        }
+        if let Some(max_messages) = limit("max_messages_per_session")? {
+            self.max_messages_per_session = size(max_messages);
+        }
+        if let Some(max_bytes) = limit("max_bytes")? {
+            self.max_bytes = size(max_bytes);
+        }
+        if let Some(ttl_minutes) = limit("ttl_minutes")? {
+            self.ttl = ttl_minutes.saturating_mul(60 * 1000);
+        }
+
+        Ok(self)
+    }
+}
+
+// Information about a session for listing sessions
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionInfo {
+    pub id: String,
+    pub title: Option<String>,
+    pub model: Option<String>,
+    pub language: Option<String>,
+
+    // Milliseconds since the Unix epoch
+    pub created_at: u64,
+    pub updated_at: u64,
+
+    // Number of questions and answers
+    pub messages: usize,
+}
+
+impl SessionInfo {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "title": self.title,
+            "model": self.model,
+            "language": self.language,
+            "created_at": self.created_at,
+            "updated_at": self.updated_at,
+            "messages": self.messages,
+        })
+    }
+}
+
+// Current size of the session store
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StoreStats {
+    pub sessions: usize,
+    pub messages: usize,
+
+    // Number of distinct documents, sessions may share one
+    pub documents: usize,
+
+    // Approximate memory used by all sessions
+    pub bytes: usize,
+}
+
+// Document a session is about, indexed for retrieval
+struct Document {
+    // Hash of the text, see `crate::util::content_hash`
+    hash: String,
+    text: String,
+    index: crate::retrieval::Index,
+
+    // Embeddings of the chunks of the index, if enabled
+    embeddings: OnceLock<VectorIndex>,
+}
+
+impl Document {
+    fn new(hash: &str, text: &str) -> Self {
+        Self {
+            hash: hash.to_string(),
+            text: text.to_string(),
+            index: crate::retrieval::Index::new(text),
+            embeddings: OnceLock::new(),
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        self.text.len()
+            + self.index.bytes()
+            + self
+                .embeddings
+                .get()
+                .map(VectorIndex::bytes)
+                .unwrap_or_default()
+    }
+
+    // The index is cheap to rebuild, so we only keep the text. Embeddings are
+    // big and computed again when the session is used, see `embed_document`.
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "text": self.text,
+        })
+    }
+}
+
+// Question used to find the relevant parts of a document
+#[derive(Debug, Clone, Default)]
+pub struct Query<'a> {
+    pub text: &'a str,
+
+    // Embedding of the question, used instead of the
+    // text if the document of the session has embeddings
+    pub embedding: Option<Vec<f32>>,
 }
+
+impl<'a> From<&'a str> for Query<'a> {
+    fn from(text: &'a str) -> Self {
+        Self {
+            text,
+            embedding: None,
+        }
+    }
+}
+
+// Messages of a session to be compacted into a recap
+#[derive(Debug, Clone)]
+pub struct Compaction {
+    // The current recap that the messages should be added to
+    pub recap: Option<String>,
+    pub messages: Vec<Message>,
+
+    // Number of evicted and chat messages that are part of the compaction
+    evicted: usize,
+    chat: usize,
+}
+
+// Approximate memory used by the given sessions in bytes,
+// documents shared between sessions are counted once
+fn store_bytes(sessions: &LruCache<String, Session>) -> usize {
+    let mut documents = HashSet::new();
+    sessions
+        .iter()
+        .map(|(_, session)| {
+            let document = match &session.document {
+                Some(document) if documents.insert(Arc::as_ptr(document)) => document.bytes(),
+                _ => 0,
+            };
+            session.bytes() + document
+        })
+        .sum()
+}
+
+// Estimated number of tokens of the given messages
+fn tokens(messages: &[Message]) -> usize {
+    messages.iter().map(Message::tokens).sum()
+}
+
+// Summary of the text of a session as shown to the user
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    // Language the summary was written in
+    pub language: String,
+    pub text: String,
+    pub category: String,
+
+    // Auto-generated follow-up questions and their answers
+    pub questions: Vec<QuestionAnswer>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuestionAnswer {
+    pub question: String,
+    pub answer: String,
+}
+
+impl Summary {
+    // Parse the JSON response of the summarize request
+    pub fn from_json(language: &str, json: &str) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(json).ok()?;
+        let field = |name: &str| value[name].as_str().unwrap_or_default().to_string();
+        let list = |name: &str| {
+            value[name]
+                .as_array()
+                .map(|items| {
+                    items
+                        .iter()
+                        .map(|item| item.as_str().unwrap_or_default().to_string())
+                        .collect::<Vec<String>>()
+                })
+                .unwrap_or_default()
+        };
+
+        let questions = list("questions")
+            .into_iter()
+            .zip(list("answers"))
+            .map(|(question, answer)| QuestionAnswer { question, answer })
+            .collect();
+
+        Some(Self {
+            language: language.to_string(),
+            text: field("summary"),
+            category: field("category"),
+            questions,
+        })
+    }
+
+    fn bytes(&self) -> usize {
+        self.language.len()
+            + self.text.len()
+            + self.category.len()
+            + self
+                .questions
+                .iter()
+                .map(|qa| qa.question.len() + qa.answer.len())
+                .sum::<usize>()
+    }
+
+    // Serialize in the format of the summarize response, so `from_json` can read it
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "language": self.language,
+            "summary": self.text,
+            "category": self.category,
+            "questions": self.questions.iter().map(|qa| &qa.question).collect::<Vec<&String>>(),
+            "answers": self.questions.iter().map(|qa| &qa.answer).collect::<Vec<&String>>(),
+        })
+    }
+
+    // System message that tells the model what the user was shown
+    pub fn message(&self) -> Message {
+        let mut text = format!(
+            "The user was shown the following summary of the text, written in {} language:\n{}\n",
+            self.language.to_uppercase(),
+            self.text
+        );
+
+        if !self.questions.is_empty() {
+            text.push_str("\nThe user was also shown these suggested questions and answers:\n");
+            for (i, qa) in self.questions.iter().enumerate() {
+                text.push_str(&format!(
+                    "{}. Question: {}\n   Answer: {}\n",
+                    i + 1,
+                    qa.question,
+                    qa.answer
+                ));
+            }
+            text.push_str(
+                "\nIf the user refers to the summary or to one of these questions by \
+                its position, e.g. \"the second question\", they mean the ones above.",
+            );
+        }
+
+        Message::system(&text)
+    }
+}
+
 // Source of a message
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MessageSource {
@@ -115,11 +1292,34 @@ pub enum MessageSource {
     User,
 }
 
+impl MessageSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MessageSource::System => "system",
+            MessageSource::Assistant => "assistant",
+            MessageSource::User => "user",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "system" => Some(MessageSource::System),
+            "assistant" => Some(MessageSource::Assistant),
+            "user" => Some(MessageSource::User),
+            _ => None,
+        }
+    }
+}
+
 // Message struct to store chat messages with their roles
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Message {
     pub text: String,
     pub source: MessageSource,
+
+    // Id of the message within its session, assigned when the message is
+    // added to the chat of a session. Prompts and new messages have id 0.
+    pub id: u64,
 }
 
 impl Message {
@@ -127,6 +1327,7 @@ impl Message {
         Self {
             text: text.to_string(),
             source,
+            id: 0,
         }
     }
 
@@ -141,4 +1342,31 @@ impl Message {
     pub fn user(text: &str) -> Self {
         Self::new(text, MessageSource::User)
     }
+
+    // Estimated number of tokens of this message
+    pub fn tokens(&self) -> usize {
+        crate::tokens::estimate(&self.text)
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "source": self.source.name(),
+            "text": self.text,
+        })
+    }
+
+    pub fn from_json(value: &serde_json::Value) -> Result<Self, anyhow::Error> {
+        let source = value["source"]
+            .as_str()
+            .and_then(MessageSource::from_name)
+            .ok_or_else(|| anyhow::anyhow!("Invalid message source: {}", value["source"]))?;
+        let text = value["text"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing message text"))?;
+
+        let mut message = Self::new(text, source);
+        message.id = value["id"].as_u64().unwrap_or_default();
+        Ok(message)
+    }
 }
@@ -10,31 +10,81 @@ const TEST_API_KEY: &str = env!("SUMMY_TEST_API_KEY");
 async fn verify_access() {
     let result = crate::verify_access(TEST_MODEL, TEST_API_KEY).await;
     assert!(result.is_ok(), "Expected Ok, got {:?}", result);
-    assert_eq!(result.unwrap(), "Access confirmed");
+
+    let report: serde_json::Value = serde_json::from_str(&result.unwrap()).unwrap();
+    assert_eq!(report["model"], TEST_MODEL);
+    assert_eq!(report["reply"], "Access confirmed");
+    assert_eq!(report["confirmed"], true);
+    assert!(report["adapter"].is_string());
+    assert!(report["endpoint"].as_str().unwrap().starts_with("http"));
+    assert!(report["latency_ms"].is_u64());
 }
 
 #[wasm_bindgen_test]
 async fn verify_access_invalid() {
-    let result = crate::verify_access("", "").await;
-    assert!(result.is_err());
+    let error = crate::verify_access("", "").await.unwrap_err();
+    assert_eq!(helpers::property(&error, "code"), "unknown_model");
+    assert_eq!(helpers::property(&error, "name"), "SummyError");
+    assert_eq!(helpers::property(&error, "model"), "");
+    assert!(helpers::reflect_get(&error, &"adapter".into()).is_null());
+
+    let error = crate::verify_access("not_a_valid_model", "")
+        .await
+        .unwrap_err();
+    assert!(!helpers::property(&error, "message").is_empty());
+    assert!(!helpers::property(&error, "detail").is_empty());
+
+    let error = crate::verify_access("gemini-2.0-flash-lite", "not_a_valid_api_key")
+        .await
+        .unwrap_err();
+    assert_eq!(helpers::property(&error, "code"), "auth_rejected");
+    assert_eq!(helpers::property(&error, "adapter"), "Gemini");
     assert_eq!(
-        result.unwrap_err(),
-        "Could not access LLM. Please verify model name and API key."
+        helpers::property(&error, "message"),
+        "The provider rejected the API key. Please verify the API key in the options."
     );
+}
 
-    let result = crate::verify_access("not_a_valid_model", "").await;
-    assert!(result.is_err());
+#[wasm_bindgen_test]
+async fn verify_access_custom_endpoint() {
+    // nothing listens on the discard port
+    crate::configure_endpoints(
+        r#"{"local-llama": {"url": "http://localhost:9/v1", "model": "llama-3.1-8b"}}"#,
+    )
+    .unwrap();
+
+    let error = crate::verify_access("local-llama", "").await.unwrap_err();
+    assert_eq!(helpers::property(&error, "code"), "network_unreachable");
+    assert_eq!(helpers::property(&error, "adapter"), "OpenAI");
     assert_eq!(
-        result.unwrap_err(),
-        "Could not access LLM. Please verify model name and API key."
+        helpers::property(&error, "endpoint"),
+        "http://localhost:9/v1/"
     );
-
-    let result = crate::verify_access("gemini-2.0-flash-lite", "not_a_valid_api_key").await;
-    assert!(result.is_err());
     assert_eq!(
-        result.unwrap_err(),
-        "Could not access LLM. Please verify model name and API key."
+        helpers::reflect_get(&error, &"retryable".into()).as_bool(),
+        Some(true)
     );
+
+    assert!(crate::configure_endpoints(r#"{"local-llama": {"url": "localhost"}}"#).is_err());
+    crate::configure_endpoints("").unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn list_models() {
+    // providers without credentials get the built-in list
+    let result = crate::list_models("DeepSeek", r#"{"openai": "sk-test"}"#)
+        .await
+        .unwrap();
+    let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(result.as_object().unwrap().len(), 1);
+    assert_eq!(result["deepseek"]["source"], "builtin");
+    let chat = &result["deepseek"]["models"][0];
+    assert_eq!(chat["name"], "deepseek-chat");
+    assert_eq!(chat["json_schema"], true);
+    assert_eq!(chat["context_size"], 64_000);
+    assert_eq!(chat["vision"], false);
+
+    assert!(crate::list_models("nope", "").await.is_err());
 }
 
 #[wasm_bindgen_test]
@@ -134,6 +184,417 @@ fn extract_text_no_html() {
     assert!(got.contains("This is another paragraph with important information."));
 }
 
+#[wasm_bindgen_test]
+fn content_type_from_mime() {
+    use crate::content::ContentType;
+
+    let tests = vec![
+        ("text/html; charset=utf-8", Some(ContentType::Html)),
+        ("text/plain", Some(ContentType::PlainText)),
+        ("text/markdown", Some(ContentType::Markdown)),
+        ("application/json", Some(ContentType::Json)),
+        ("application/ld+json", Some(ContentType::Json)),
+        ("image/png", None),
+    ];
+
+    for (mime, expected) in tests {
+        assert_eq!(ContentType::from_mime(mime), expected, "mime: {}", mime);
+    }
+
+    // text we don't know is sniffed instead of rejected
+    let tests = vec![
+        (
+            "text/xml",
+            "<html><body><p>Hi</p></body></html>",
+            Some(ContentType::Html),
+        ),
+        (
+            "application/rss+xml; charset=utf-8",
+            "Just some words",
+            Some(ContentType::PlainText),
+        ),
+        (
+            "text/x-log",
+            "Just some words",
+            Some(ContentType::PlainText),
+        ),
+        ("", r#"{"name": "summy"}"#, Some(ContentType::Json)),
+        ("image/png", "Just some words", None),
+    ];
+
+    for (mime, content, expected) in tests {
+        assert_eq!(
+            ContentType::detect(mime, content),
+            expected,
+            "mime: {}",
+            mime
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn content_type_sniff() {
+    use crate::content::ContentType;
+
+    let tests = vec![
+        (r#"{"name": "summy", "version": 3}"#, ContentType::Json),
+        (
+            "<!DOCTYPE html><html><body><p>Hi</p></body></html>",
+            ContentType::Html,
+        ),
+        (
+            "# Summy\n\nSummarizes pages.\n\n## Usage\n\n- Click the button",
+            ContentType::Markdown,
+        ),
+        (
+            "Just some plain text.\nWith two lines.",
+            ContentType::PlainText,
+        ),
+        // braces alone don't make a JSON document
+        ("{ not json", ContentType::PlainText),
+    ];
+
+    for (content, expected) in tests {
+        assert_eq!(
+            ContentType::sniff(content),
+            expected,
+            "content: {}",
+            content
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn extract_markdown() {
+    let markdown =
+        "# Summy\n\nSummarizes *web pages*.\n\n## Usage\n\n- Click the button\n- Read the summary";
+
+    let result = crate::content::extract(markdown, crate::content::ContentType::Markdown);
+    assert!(result.is_ok(), "Expected Ok, got {:?}", result);
+
+    let got = result.unwrap();
+    assert!(got.starts_with("# Summy"), "Got {:?}", got);
+    assert!(got.contains("Summarizes web pages."), "Got {:?}", got);
+    assert!(got.contains("## Usage"), "Got {:?}", got);
+    assert!(got.contains("- Click the button"), "Got {:?}", got);
+    assert!(got.contains("- Read the summary"), "Got {:?}", got);
+}
+
+#[wasm_bindgen_test]
+fn extract_markdown_blockquote() {
+    let markdown = "Intro\n\n> First line\n> continued\n>\n> Second paragraph\n\nOutro";
+
+    let result = crate::content::extract(markdown, crate::content::ContentType::Markdown);
+    assert!(result.is_ok(), "Expected Ok, got {:?}", result);
+
+    let got = result.unwrap();
+    assert!(
+        got.contains("Intro\n\n> First line continued\n>\n> Second paragraph\n\nOutro"),
+        "Got {:?}",
+        got
+    );
+}
+
+#[wasm_bindgen_test]
+fn extract_json() {
+    let json = r#"{"name":"summy","tags":["rust","wasm"]}"#;
+
+    let result = crate::content::extract(json, crate::content::ContentType::Json);
+    assert!(result.is_ok(), "Expected Ok, got {:?}", result);
+    assert_eq!(
+        result.unwrap(),
+        "{\n  \"name\": \"summy\",\n  \"tags\": [\n    \"rust\",\n    \"wasm\"\n  ]\n}"
+    );
+
+    let result = crate::content::extract("{ not json", crate::content::ContentType::Json);
+    assert!(result.is_err());
+}
+
+#[wasm_bindgen_test]
+fn parse_feed_rss() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+            <channel>
+                <title>Engineering Blog</title>
+                <item>
+                    <title>Scaling our database</title>
+                    <link>https://example.com/scaling</link>
+                    <pubDate>Mon, 03 Mar 2025 10:00:00 GMT</pubDate>
+                    <description>Short teaser</description>
+                    <content:encoded><![CDATA[<p>How we sharded our database.</p>]]></content:encoded>
+                </item>
+                <item>
+                    <title>Hiring update</title>
+                    <link>https://example.com/hiring</link>
+                    <description><![CDATA[<p>We are hiring.</p>]]></description>
+                </item>
+            </channel>
+        </rss>"#;
+
+    let result = crate::feed::parse(xml);
+    assert!(result.is_ok(), "Expected Ok, got {:?}", result);
+
+    let items = result.unwrap();
+    assert_eq!(items.len(), 2);
+
+    // Full content should be preferred over the description
+    assert_eq!(items[0].title, "Scaling our database");
+    assert_eq!(items[0].link, "https://example.com/scaling");
+    assert_eq!(
+        items[0].date.as_deref(),
+        Some("Mon, 03 Mar 2025 10:00:00 GMT")
+    );
+    assert_eq!(items[0].content, "<p>How we sharded our database.</p>");
+
+    // Description should be used if there is no full content
+    assert_eq!(items[1].title, "Hiring update");
+    assert_eq!(items[1].date, None);
+    assert_eq!(items[1].content, "<p>We are hiring.</p>");
+}
+
+#[wasm_bindgen_test]
+fn parse_feed_atom() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+        <feed xmlns="http://www.w3.org/2005/Atom">
+            <title>Engineering Blog</title>
+            <entry>
+                <title>Rewriting it in Rust</title>
+                <link rel="self" href="https://example.com/feed/rust"/>
+                <link rel="alternate" href="https://example.com/rust"/>
+                <updated>2025-03-04T12:00:00Z</updated>
+                <summary>Teaser</summary>
+                <content type="html">&lt;p&gt;Why we moved to Rust.&lt;/p&gt;</content>
+            </entry>
+        </feed>"#;
+
+    let result = crate::feed::parse(xml);
+    assert!(result.is_ok(), "Expected Ok, got {:?}", result);
+
+    let items = result.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].title, "Rewriting it in Rust");
+    assert_eq!(items[0].link, "https://example.com/rust");
+    assert_eq!(items[0].date.as_deref(), Some("2025-03-04T12:00:00Z"));
+    assert_eq!(items[0].content, "<p>Why we moved to Rust.</p>");
+}
+
+#[wasm_bindgen_test]
+fn feed_description_text() {
+    assert_eq!(
+        crate::feed::strip_tags("<p>Short <b>news</b>&nbsp;&amp; more</p>\n<br/>"),
+        "Short news & more"
+    );
+    assert_eq!(crate::feed::strip_tags("plain text"), "plain text");
+}
+
+#[wasm_bindgen_test]
+fn parse_feed_invalid() {
+    let result = crate::feed::parse("<html><body>Not a feed</body></html>");
+    assert!(result.is_err());
+}
+
+#[wasm_bindgen_test]
+fn parse_pdf() {
+    use crate::pdf::PdfSection;
+
+    // Three pages with a running header and page numbers, the second page has two columns
+    let bytes = helpers::pdf(&[
+        &[
+            ("Journal of Cats", 8.0, 72.0, 760.0),
+            ("Deep Learning for Cats", 20.0, 72.0, 700.0),
+            ("Abstract", 14.0, 72.0, 660.0),
+            ("We study feline im-", 10.0, 72.0, 640.0),
+            ("age classification.", 10.0, 72.0, 628.0),
+            ("1", 8.0, 300.0, 40.0),
+        ],
+        &[
+            ("Journal of Cats", 8.0, 72.0, 760.0),
+            ("Left column first line", 10.0, 72.0, 700.0),
+            ("Right column first line", 10.0, 320.0, 700.0),
+            ("left column second line.", 10.0, 72.0, 688.0),
+            ("right column second line.", 10.0, 320.0, 688.0),
+            ("2", 8.0, 300.0, 40.0),
+        ],
+        &[
+            ("Journal of Cats", 8.0, 72.0, 760.0),
+            ("References", 14.0, 72.0, 700.0),
+            ("Whiskers et al. Cats in the wild.", 10.0, 72.0, 680.0),
+            ("3", 8.0, 300.0, 40.0),
+        ],
+    ]);
+
+    let result = crate::pdf::parse(&bytes);
+    assert!(result.is_ok(), "Expected Ok, got {:?}", result);
+
+    let doc = result.unwrap();
+    assert_eq!(doc.title.as_deref(), Some("Deep Learning for Cats"));
+    assert_eq!(doc.pages.len(), 3);
+
+    // Running headers and page numbers are removed, hyphenated words are joined
+    assert_eq!(
+        doc.pages[0].paragraphs,
+        vec![
+            "Deep Learning for Cats",
+            "Abstract",
+            "We study feline image classification."
+        ]
+    );
+
+    // Left column is read before the right column
+    assert_eq!(
+        doc.pages[1].paragraphs,
+        vec![
+            "Left column first line left column second line.",
+            "Right column first line right column second line."
+        ]
+    );
+
+    assert_eq!(
+        doc.sections,
+        vec![
+            PdfSection {
+                title: "Abstract".to_string(),
+                page: 1
+            },
+            PdfSection {
+                title: "References".to_string(),
+                page: 3
+            },
+        ]
+    );
+
+    let text = doc.to_text();
+    assert!(text.contains("[p. 1]"));
+    assert!(text.contains("## Abstract"));
+    assert!(text.contains("[p. 3]\n## References"));
+}
+
+#[wasm_bindgen_test]
+fn detect_paper() {
+    // Citation meta tags identify a paper
+    let html =
+        r#"<html><head><meta name="citation_title" content="Cats"></head><body>Cats</body></html>"#;
+    assert!(crate::paper::detect(html, "Cats"));
+
+    // So do typical sections
+    let text = "Deep Learning for Cats\nAbstract\nWe study cats.\n1. Introduction\nCats are great.\n2 Methods\nWe trained a model.\n3 Results\nIt works.\nReferences\n[1] Whiskers. Cats.";
+    assert!(crate::paper::detect("", text));
+
+    // Also without numbered headings
+    let text = "Deep Learning for Cats\nAbstract\nWe study cats.\nIntroduction\nCats are great.\nMethods\nWe trained a model.\nResults\nIt works.\nConclusion\nCats win.";
+    assert!(crate::paper::detect("", text));
+
+    // Neither is a page linking to a paper
+    let html = r#"<html><body><p>See <a href="https://arxiv.org/abs/2401.00001">the paper</a>.</p></body></html>"#;
+    assert!(!crate::paper::detect(html, "See the paper."));
+
+    // A regular article is not a paper
+    let text = "Climate change refers to long-term changes in temperature.\nResults of a new study show...";
+    assert!(!crate::paper::detect("", text));
+}
+
+#[wasm_bindgen_test]
+fn paper_section_names() {
+    // Numbering is stripped, but not the leading "I" of a heading
+    assert!(crate::paper::is_section_name("Introduction"));
+    assert!(crate::paper::is_section_name("II. Methods"));
+    assert!(crate::paper::is_section_name("IV DISCUSSION"));
+    assert!(crate::paper::is_section_name("3.1 Results"));
+    assert!(!crate::paper::is_section_name("Intro"));
+}
+
+#[wasm_bindgen_test]
+fn paper_sections() {
+    let text = "Deep Learning for Cats\nAbstract\nWe study cats [1].\n1. Introduction\nCats are great [2, 3].\nPrior work [1-3] and [1].\nReferences\n[1] Whiskers. Cats in the wild.\n[2] Paws. Indoor cats.\n[3] Tail. Cat tails.";
+
+    let sections = crate::paper::sections(text);
+    let headings = sections
+        .iter()
+        .map(|s| s.heading.as_str())
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        headings,
+        vec!["", "Abstract", "1. Introduction", "References"]
+    );
+
+    // Reference 1 is cited 3 times, 2 and 3 twice each
+    let cited = crate::paper::most_cited(&sections, 2);
+    assert_eq!(cited.len(), 2);
+    assert_eq!(cited[0].number, 1);
+    assert_eq!(cited[0].count, 3);
+    assert_eq!(cited[0].text, "Whiskers. Cats in the wild.");
+    assert_eq!(cited[1].number, 2);
+    assert_eq!(cited[1].count, 2);
+
+    let got = crate::paper::to_text(text);
+    assert!(
+        got.contains("## Abstract\nWe study cats [1]."),
+        "Got {:?}",
+        got
+    );
+    assert!(
+        got.contains("## Most cited references\n[1] (cited 3 times) Whiskers. Cats in the wild."),
+        "Got {:?}",
+        got
+    );
+}
+
+#[wasm_bindgen_test]
+fn detect_legal() {
+    // A legal title identifies terms of service and privacy policies
+    let text = "Privacy Policy\nLast updated: March 2025\nWe respect your privacy.";
+    assert!(crate::legal::detect("", text));
+
+    let html =
+        "<html><head><title>Terms of Use - Example</title></head><body>Welcome</body></html>";
+    assert!(crate::legal::detect(html, "Welcome"));
+
+    // So do clauses of most risk categories
+    let text = "We may share your information with third parties. We use cookies. \
+        Your subscription will automatically renew. Disputes are resolved by binding arbitration. \
+        We may terminate your account at our sole discretion.";
+    assert!(crate::legal::detect("", text));
+
+    // A regular article is not a legal document
+    let text = "Climate change refers to long-term changes in temperature.";
+    assert!(!crate::legal::detect("", text));
+
+    // Neither is an article behind a cookie banner that mentions
+    // legal documents and uses words of several risk categories
+    let text = "We use cookies to improve your experience. By continuing you accept our \
+        Privacy Policy and Terms of Service.\nStreaming prices keep rising\n\
+        Subscription renewal prices went up again this year. Tracking pixels and \
+        third party affiliates are common in ads, and the termination of recurring \
+        plans without notice led to a class action about perpetual licenses.";
+    assert!(!crate::legal::detect("", text));
+}
+
+#[wasm_bindgen_test]
+fn verify_legal_quotes() {
+    let text = "Your subscription will automatically renew each month.\n\
+        We may share your   data with \"trusted\" partners.";
+    let response = r#"{
+        "summary": "Summary",
+        "risks": [
+            {"category": "auto_renewal", "quote": "Your subscription will automatically renew each month."},
+            {"category": "data_sharing", "quote": "We may share your data with trusted partners"},
+            {"category": "tracking", "quote": "We track your location."}
+        ]
+    }"#;
+
+    let got = crate::legal::verify_quotes(response, text);
+    let value: serde_json::Value = serde_json::from_str(&got).unwrap();
+    let risks = value["risks"].as_array().unwrap();
+
+    // Whitespace, case and quotation marks are ignored, invented quotes are not verified
+    assert_eq!(risks[0]["verified"], true);
+    assert_eq!(risks[1]["verified"], true);
+    assert_eq!(risks[2]["verified"], false);
+
+    // Invalid JSON is returned as is
+    assert_eq!(crate::legal::verify_quotes("not json", text), "not json");
+}
+
 #[wasm_bindgen_test]
 async fn summarize_english() {
     let html = r#"
@@ -167,8 +628,10 @@ async fn summarize_english() {
     helpers::assert_summary_response(&got, "climate change");
 
     // Validate session was initialized correctly with the expected context window
-    let context = crate::session::STORE.context_window(session_id).unwrap();
-    assert_eq!(context.len(), 2);
+    let context = crate::session::STORE
+        .context_window_within(session_id, None, usize::MAX)
+        .unwrap();
+    assert_eq!(context.len(), 3);
 
     // First message in the context window should be the text extracted from the HTML
     let text = context[0].text.to_lowercase();
@@ -191,6 +654,17 @@ async fn summarize_english() {
         prompt
     );
     assert_eq!(context[1].source, MessageSource::System);
+
+    // Third message in the context window should be the summary the user was shown
+    let summary = crate::session::STORE
+        .transcript(session_id)
+        .and_then(|transcript| transcript.summary)
+        .unwrap();
+    assert_eq!(summary.language.to_lowercase(), "english");
+    assert_eq!(summary.questions.len(), 3);
+    assert_eq!(context[2], summary.message());
+    assert!(context[2].text.contains(&summary.questions[1].question));
+    assert_eq!(context[2].source, MessageSource::System);
 }
 
 #[wasm_bindgen_test]
@@ -330,7 +804,8 @@ fn multiple_sessions() {
     }
 
     for i in 0..10 {
-        let result = crate::session::STORE.context_window(&format!("thread-{}", i));
+        let result =
+            crate::session::STORE.context_window_within(&format!("thread-{}", i), None, usize::MAX);
         assert!(result.is_some(), "Expected Some, got {:?}", result);
 
         let context = result.unwrap();
@@ -350,7 +825,7 @@ fn message_eviction() {
     }
 
     // Validate that the context window has 100 messages
-    let context = crate::session::STORE.context_window("id");
+    let context = crate::session::STORE.context_window_within("id", None, usize::MAX);
     assert!(context.is_some(), "Expected Some, got {:?}", context);
 
     // Validate that the context window has exactly 100 messages
@@ -366,7 +841,7 @@ fn message_eviction() {
     crate::session::STORE.append_messages("id", vec![Message::user("This is the latest message")]);
 
     // Validate that the oldest message was evicted
-    let context = crate::session::STORE.context_window("id");
+    let context = crate::session::STORE.context_window_within("id", None, usize::MAX);
     assert!(context.is_some(), "Expected Some, got {:?}", context);
 
     // Validate that the context window still has 100 messages
@@ -397,7 +872,11 @@ fn session_eviction() {
 
     // Validate that all sessions were created
     for i in 0..100 {
-        let context = crate::session::STORE.context_window(&format!("session-{}", i));
+        let context = crate::session::STORE.context_window_within(
+            &format!("session-{}", i),
+            None,
+            usize::MAX,
+        );
         assert!(context.is_some(), "Expected Some, got {:?}", context);
 
         let context = context.unwrap();
@@ -409,7 +888,11 @@ fn session_eviction() {
     let excluded = 50;
     for i in 0..100 {
         if i != excluded {
-            crate::session::STORE.context_window(&format!("session-{}", i));
+            crate::session::STORE.context_window_within(
+                &format!("session-{}", i),
+                None,
+                usize::MAX,
+            );
         }
     }
 
@@ -421,11 +904,15 @@ fn session_eviction() {
 
     // Validate that the least recently used session, i.e. the one we excluded
     // in the last access loop above, was evicted
-    let context = crate::session::STORE.context_window(&format!("session-{}", excluded));
+    let context = crate::session::STORE.context_window_within(
+        &format!("session-{}", excluded),
+        None,
+        usize::MAX,
+    );
     assert!(context.is_none(), "Expected None, got {:?}", context);
 
     // Validate that the latest session was created
-    let context = crate::session::STORE.context_window("new-session");
+    let context = crate::session::STORE.context_window_within("new-session", None, usize::MAX);
     assert!(context.is_some(), "Expected Some, got {:?}", context);
 
     let context = context.unwrap();
@@ -439,25 +926,1201 @@ fn session_removal() {
     crate::session::STORE.create_session("two", vec![]);
 
     // Validate that the session was created
-    let context = crate::session::STORE.context_window("one");
+    let context = crate::session::STORE.context_window_within("one", None, usize::MAX);
     assert!(context.is_some(), "Expected Some, got {:?}", context);
 
     // Remove the session
     crate::session::STORE.remove_session("one");
 
     // Validate that the session was removed
-    let context = crate::session::STORE.context_window("one");
+    let context = crate::session::STORE.context_window_within("one", None, usize::MAX);
     assert!(context.is_none(), "Expected None, got {:?}", context);
 
     // Validate that the other session is still present
-    let context = crate::session::STORE.context_window("two");
+    let context = crate::session::STORE.context_window_within("two", None, usize::MAX);
     assert!(context.is_some(), "Expected Some, got {:?}", context);
 }
 
+#[wasm_bindgen_test]
+fn session_summary() {
+    let json = r#"{
+        "summary": "Cats sleep a lot.",
+        "category": "Animals",
+        "questions": ["Why do cats sleep?", "How long do cats sleep?", "Do kittens sleep more?"],
+        "answers": ["To save energy.", "About 15 hours a day.", "Yes, up to 20 hours."]
+    }"#;
+
+    let summary = crate::session::Summary::from_json("English", json).unwrap();
+    assert_eq!(summary.text, "Cats sleep a lot.");
+    assert_eq!(summary.category, "Animals");
+    assert_eq!(summary.questions.len(), 3);
+    assert_eq!(summary.questions[1].question, "How long do cats sleep?");
+    assert_eq!(summary.questions[1].answer, "About 15 hours a day.");
+
+    crate::session::STORE.create_session("summary", vec![Message::user("Cats sleep.")]);
+    crate::session::STORE.set_summary("summary", summary);
+    crate::session::STORE.append_messages(
+        "summary",
+        vec![Message::user("Explain the second question")],
+    );
+
+    // The summary comes right after the prompts and before the chat
+    let context = crate::session::STORE
+        .context_window_within("summary", None, usize::MAX)
+        .unwrap();
+    assert_eq!(context.len(), 3);
+    assert_eq!(context[1].source, MessageSource::System);
+    assert!(context[1].text.contains("ENGLISH"));
+    assert!(context[1].text.contains("Cats sleep a lot."));
+    assert!(context[1]
+        .text
+        .contains("2. Question: How long do cats sleep?"));
+    assert!(context[1].text.contains("Answer: About 15 hours a day."));
+    assert_eq!(context[2].text, "Explain the second question");
+
+    // Responses that aren't JSON have no summary
+    assert!(crate::session::Summary::from_json("English", "not json").is_none());
+}
+
+#[wasm_bindgen_test]
+fn token_estimates() {
+    assert_eq!(crate::tokens::estimate(""), 0);
+    assert_eq!(crate::tokens::estimate("abcd"), 1);
+    assert_eq!(crate::tokens::estimate("abcde"), 2);
+    assert_eq!(crate::tokens::estimate("기후 변화"), 5);
+
+    assert_eq!(crate::tokens::context_size("gemini-2.0-flash"), 1_048_576);
+    assert_eq!(crate::tokens::context_size("gpt-4o-mini"), 128_000);
+    assert_eq!(crate::tokens::context_size("gpt-4"), 8_192);
+    assert_eq!(crate::tokens::context_size("ollama::llama3.2"), 8_192);
+    assert_eq!(crate::tokens::context_size("unknown-model"), 8_192);
+    assert!(crate::tokens::budget("gpt-4") < 8_192);
+}
+
+#[wasm_bindgen_test]
+fn context_window_within_budget() {
+    let store = &crate::session::STORE;
+    store.create_session("budget", vec![Message::user(&"a".repeat(400))]);
+
+    // 10 messages with 25 tokens each
+    for i in 0..10 {
+        store.append_messages("budget", vec![Message::user(&format!("{:0>100}", i))]);
+    }
+
+    // The prompt takes 100 tokens, leaving room for the 4 newest messages
+    let context = store.context_window_within("budget", None, 200).unwrap();
+    assert_eq!(context.len(), 5);
+    assert_eq!(context[0].text, "a".repeat(400));
+    assert_eq!(context[1].text, format!("{:0>100}", 6));
+    assert_eq!(context[4].text, format!("{:0>100}", 9));
+
+    // Without a budget all messages are included
+    let context = store
+        .context_window_within("budget", None, usize::MAX)
+        .unwrap();
+    assert_eq!(context.len(), 11);
+}
+
+#[wasm_bindgen_test]
+fn session_compaction() {
+    let store = &crate::session::STORE;
+    store.create_session("compaction", vec![Message::user(&"a".repeat(400))]);
+
+    for i in 0..10 {
+        store.append_messages("compaction", vec![Message::user(&format!("{:0>100}", i))]);
+    }
+
+    // Prompt and messages take 350 tokens, well within the budget
+    assert!(store.compaction("compaction", 1000).is_none());
+
+    // With a budget of 400 tokens we are above the threshold, the newest messages
+    // that fit into half of the remaining 300 tokens are kept, i.e. 6 messages
+    let compaction = store.compaction("compaction", 400).unwrap();
+    assert_eq!(compaction.recap, None);
+    assert_eq!(compaction.messages.len(), 4);
+    assert_eq!(compaction.messages[0].text, format!("{:0>100}", 0));
+    assert_eq!(compaction.messages[3].text, format!("{:0>100}", 3));
+
+    store.compact("compaction", &compaction, "The user counted to three.");
+
+    // The recap replaces the compacted messages
+    let context = store
+        .context_window_within("compaction", None, usize::MAX)
+        .unwrap();
+    assert_eq!(context.len(), 8);
+    assert_eq!(context[1].source, MessageSource::System);
+    assert!(context[1].text.contains("The user counted to three."));
+    assert_eq!(context[2].text, format!("{:0>100}", 4));
+
+    // Messages pushed out of the chat are compacted instead of being dropped
+    store.create_session("overflow", vec![]);
+    for i in 0..101 {
+        store.append_messages("overflow", vec![Message::user(&format!("message {}", i))]);
+    }
+
+    let compaction = store.compaction("overflow", 100_000).unwrap();
+    assert_eq!(compaction.messages.len(), 1);
+    assert_eq!(compaction.messages[0].text, "message 0");
+
+    store.compact("overflow", &compaction, "The user started counting.");
+    let context = store
+        .context_window_within("overflow", None, usize::MAX)
+        .unwrap();
+    assert_eq!(context.len(), 101);
+    assert!(context[0].text.contains("The user started counting."));
+    assert_eq!(context[1].text, "message 1");
+    assert!(store.compaction("overflow", 100_000).is_none());
+}
+
+#[wasm_bindgen_test]
+fn chunk_text() {
+    let text = format!(
+        "{}\n\n{}\n{}",
+        "a ".repeat(300),
+        "b ".repeat(50),
+        "c ".repeat(50)
+    );
+    let chunks = crate::retrieval::chunk(&text, 100);
+
+    // the long paragraph is split, the short ones are merged
+    assert!(chunks.len() >= 2);
+    assert!(chunks.iter().all(|c| crate::tokens::estimate(c) <= 100));
+    assert!(chunks.last().unwrap().contains('b') && chunks.last().unwrap().contains('c'));
+}
+
+#[wasm_bindgen_test]
+fn chunk_pdf_pages() {
+    let text = format!(
+        "[p. 1]\n{}\n\n[p. 2]\n{}\n\n{}",
+        "a ".repeat(400),
+        "b ".repeat(400),
+        "c ".repeat(400)
+    );
+    let chunks = crate::retrieval::chunk(&text, 100);
+
+    // every chunk says which page it's on, also those in the middle of a page
+    assert!(chunks.len() >= 4);
+    assert!(chunks.iter().all(|c| c.starts_with("[p. ")));
+    assert!(chunks.last().unwrap().starts_with("[p. 2]\nc"));
+    assert_eq!(chunks[1].matches("[p. ").count(), 1);
+
+    assert!(crate::pdf::is_page_marker("[p. 12]"));
+    assert!(!crate::pdf::is_page_marker("[p. 1] Introduction"));
+    assert!(!crate::pdf::is_page_marker("[1] Whiskers. Cats."));
+}
+
+#[wasm_bindgen_test]
+fn search_chunks() {
+    let filler = "filler ".repeat(100);
+    let text = format!(
+        "Cats sleep most of the day. {filler}\n\
+        Dogs like to play fetch in the park. {filler}\n\
+        The park opens at nine, dogs must be on a leash. {filler}"
+    );
+    let index = crate::retrieval::Index::new(&text);
+    assert_eq!(index.chunks().len(), 3);
+
+    let found = index.search("When does the park open for dogs?", 2);
+    assert_eq!(found, vec![1, 2]);
+    assert!(index.search("elephants", 2).is_empty());
+}
+
+#[wasm_bindgen_test]
+fn retrieval_context() {
+    let store = &crate::session::STORE;
+    store.create_session("retrieval", vec![Message::system("Follow up")]);
+
+    let mut text = String::new();
+    for i in 0..20 {
+        text.push_str(&format!(
+            "Paragraph {} is about topic{}. {}\n\n",
+            i,
+            i,
+            "filler ".repeat(150)
+        ));
+    }
+    store.set_document("retrieval", &text);
+
+    // the full document without a query
+    let context = store
+        .context_window_within("retrieval", None, usize::MAX)
+        .unwrap();
+    assert_eq!(context[0].text, text);
+
+    // only the relevant excerpts for a question
+    let context = store
+        .context_window_within("retrieval", Some("What about topic7?".into()), 100_000)
+        .unwrap();
+    assert!(context[0].text.contains("topic7"));
+    assert!(!context[0].text.contains("topic12"));
+    assert_eq!(context[1].text, "Follow up");
+
+    // the full document if requested
+    store.set_full_document("retrieval", true);
+    let context = store
+        .context_window_within("retrieval", Some("What about topic7?".into()), 100_000)
+        .unwrap();
+    assert_eq!(context[0].text, text);
+}
+
+#[wasm_bindgen_test]
+fn vector_search() {
+    let index =
+        crate::embedding::VectorIndex::new(vec![vec![1.0, 0.0], vec![0.0, 2.0], vec![1.0, 1.0]])
+            .unwrap();
+    assert_eq!(index.len(), 3);
+
+    // most similar first by cosine, returned in document order
+    assert_eq!(index.search(&[3.0, 0.1], 2), vec![0, 2]);
+    assert_eq!(index.search(&[-1.0, 0.0], 2), Vec::<usize>::new());
+
+    // vectors need to have the same dimensions
+    assert!(index.search(&[1.0, 0.0, 0.0], 2).is_empty());
+    assert!(crate::embedding::VectorIndex::new(vec![vec![1.0], vec![1.0, 0.0]]).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn embedding_context() {
+    use crate::embedding::Embedder;
+
+    let store = &crate::session::STORE;
+    store.create_session("embedding", vec![Message::system("Follow up")]);
+
+    let mut text = String::new();
+    for i in 0..20 {
+        text.push_str(&format!(
+            "Paragraph {} is about topic{}. {}\n\n",
+            i,
+            i,
+            "filler ".repeat(150)
+        ));
+    }
+    store.set_document("embedding", &text);
+    assert!(!store.has_embeddings("embedding"));
+
+    let chunks = store.unembedded_chunks("embedding").unwrap();
+    let index = crate::embedding::index(&crate::embedding::FakeEmbedder, &chunks)
+        .await
+        .unwrap();
+    store.set_embeddings("embedding", index);
+    assert!(store.has_embeddings("embedding"));
+    assert!(store.unembedded_chunks("embedding").is_none());
+
+    // the embedding is used instead of the words of the question
+    let embedding = crate::embedding::FakeEmbedder
+        .embed(&["topic3".to_string()])
+        .await
+        .unwrap()
+        .pop();
+    let query = crate::session::Query {
+        text: "What about topic12?",
+        embedding,
+    };
+    let context = store
+        .context_window_within("embedding", Some(query), 100_000)
+        .unwrap();
+    assert!(context[0].text.contains("topic3"));
+    assert!(!context[0].text.contains("topic12"));
+}
+
+#[wasm_bindgen_test]
+fn save_and_restore_sessions() {
+    let store = &crate::session::STORE;
+    store.create_session("saved", vec![Message::system("Follow up")]);
+    store.set_document("saved", "Cats sleep a lot.");
+    let summary = r#"{"summary": "Cats sleep.", "category": "Pets", "questions": ["Why?"], "answers": ["Because."]}"#;
+    store.set_summary(
+        "saved",
+        crate::session::Summary::from_json("english", summary).unwrap(),
+    );
+    store.append_messages(
+        "saved",
+        vec![Message::user("How long?"), Message::assistant("16 hours.")],
+    );
+    let context = store
+        .context_window_within("saved", None, usize::MAX)
+        .unwrap();
+
+    let json = store.to_json_within(usize::MAX).to_string();
+    store.remove_session("saved");
+    assert!(store
+        .context_window_within("saved", None, usize::MAX)
+        .is_none());
+
+    assert!(store.restore(&json).unwrap() >= 1);
+    assert_eq!(
+        store
+            .context_window_within("saved", None, usize::MAX)
+            .unwrap(),
+        context
+    );
+    assert_eq!(
+        store
+            .transcript("saved")
+            .and_then(|transcript| transcript.summary)
+            .unwrap()
+            .questions[0]
+            .answer,
+        "Because."
+    );
+
+    // sessions that exist already are kept
+    store.append_messages("saved", vec![Message::user("Really?")]);
+    store.restore(&json).unwrap();
+    assert_eq!(
+        store
+            .context_window_within("saved", None, usize::MAX)
+            .unwrap()
+            .len(),
+        context.len() + 1
+    );
+
+    assert!(store.restore("{}").is_err());
+    assert!(store
+        .restore(
+            r#"{"version": 2, "sessions": [{"id": "x", "chat": [{"source": "bot", "text": ""}]}]}"#
+        )
+        .is_err());
+}
+
+#[wasm_bindgen_test]
+fn session_expiry() {
+    let store = &crate::session::STORE;
+    let old = r#"{"version": 2, "sessions": [
+        {"id": "stale", "created_at": 0, "last_used": 0, "prompts": [{"source": "system", "text": "Follow up"}]}
+    ]}"#;
+
+    // expired sessions are removed on access
+    store.restore(old).unwrap();
+    assert!(store
+        .context_window_within("stale", None, usize::MAX)
+        .is_none());
+    assert!(store.is_expired("stale"));
+
+    // and when sweeping
+    store.remove_session("stale");
+    store.restore(old).unwrap();
+    store.create_session("fresh", vec![Message::system("Follow up")]);
+    assert!(store.sweep_expired() >= 1);
+    assert!(store.is_expired("stale"));
+    assert!(store
+        .context_window_within("fresh", None, usize::MAX)
+        .is_some());
+    assert!(!store.is_expired("fresh"));
+    assert!(!store.is_expired("unknown"));
+
+    // a new session with the same id is not expired
+    store.create_session("stale", vec![]);
+    assert!(!store.is_expired("stale"));
+    assert!(store
+        .context_window_within("stale", None, usize::MAX)
+        .is_some());
+}
+
+#[wasm_bindgen_test]
+fn store_limits() {
+    let store = &crate::session::STORE;
+    let defaults = store.config();
+
+    // the oldest messages are moved out of the chat
+    store.configure(crate::session::StoreConfig {
+        max_messages_per_session: 2,
+        ..defaults
+    });
+    store.create_session("limits", vec![Message::system("Follow up")]);
+    store.append_messages(
+        "limits",
+        vec![
+            Message::user("a"),
+            Message::assistant("b"),
+            Message::user("c"),
+        ],
+    );
+    let context = store
+        .context_window_within("limits", None, usize::MAX)
+        .unwrap();
+    assert_eq!(context.len(), 3);
+    assert_eq!(context[1].text, "b");
+    assert!(store.stats().messages >= 4);
+
+    // least recently used sessions are evicted to stay within the byte limit,
+    // the most recently used session is kept even if it is too large
+    store.configure(crate::session::StoreConfig {
+        max_bytes: 1_000,
+        ..defaults
+    });
+    store.create_session("large", vec![]);
+    store.set_document("large", &"word ".repeat(1_000));
+
+    let stats = store.stats();
+    assert_eq!(stats.sessions, 1);
+    assert!(stats.bytes > 5_000);
+    assert!(store
+        .context_window_within("limits", None, usize::MAX)
+        .is_none());
+    assert!(store
+        .context_window_within("large", None, usize::MAX)
+        .is_some());
+
+    store.configure(defaults);
+
+    // limits can be changed with JSON
+    let config = defaults
+        .merge_json(r#"{"max_sessions": 3, "ttl_minutes": 1}"#)
+        .unwrap();
+    assert_eq!(config.max_sessions, 3);
+    assert_eq!(config.ttl, 60_000);
+    assert_eq!(config.max_bytes, defaults.max_bytes);
+    assert!(defaults.merge_json(r#"{"max_sessions": 0}"#).is_err());
+    assert!(defaults.merge_json("[3]").is_err());
+    assert!(crate::configure_store("3").is_err());
+
+    // huge limits don't overflow
+    let config = defaults
+        .merge_json(&format!(r#"{{"ttl_minutes": {}}}"#, u64::MAX))
+        .unwrap();
+    assert_eq!(config.ttl, u64::MAX);
+}
+
+#[wasm_bindgen_test]
+fn session_transcript() {
+    let store = &crate::session::STORE;
+    store.create_session("transcript", vec![Message::system("Follow up")]);
+    store.set_title("transcript", Some("Cats & Dogs"));
+    store.set_model("transcript", "gpt-4o");
+    let summary = r#"{"summary": "Cats sleep.", "category": "Pets", "questions": ["Why?"], "answers": ["Because."]}"#;
+    store.set_summary(
+        "transcript",
+        crate::session::Summary::from_json("english", summary).unwrap(),
+    );
+    store.append_messages(
+        "transcript",
+        vec![
+            Message::user("How long?"),
+            Message::assistant("About <script>alert(1)</script> 16 hours."),
+            Message::user("Sources?"),
+            Message::assistant(
+                "[Cat facts](https://example.com/cats), [more](javascript:alert(1)) \
+                and ![a cat](https://example.com/cat.png)",
+            ),
+        ],
+    );
+
+    let info = store.info("transcript").unwrap();
+    assert_eq!(info.title.as_deref(), Some("Cats & Dogs"));
+    assert_eq!(info.model.as_deref(), Some("gpt-4o"));
+    assert_eq!(info.language.as_deref(), Some("english"));
+    assert_eq!(info.messages, 4);
+    assert!(info.updated_at >= info.created_at);
+    assert!(store.list().iter().any(|info| info.id == "transcript"));
+    assert!(store.info("unknown").is_none());
+
+    let transcript = store.transcript("transcript").unwrap();
+    let markdown = transcript.render(crate::transcript::Format::Markdown);
+    assert!(markdown.starts_with("# Cats & Dogs\n"));
+    assert!(markdown.contains("## Summary\n\nCats sleep."));
+    assert!(markdown.contains("**Why?**\n\nBecause."));
+    assert!(markdown.contains("**You:** How long?"));
+    assert!(!markdown.contains("Follow up"));
+
+    let json: serde_json::Value =
+        serde_json::from_str(&transcript.render(crate::transcript::Format::Json)).unwrap();
+    assert_eq!(json["summary"]["questions"][0]["answer"], "Because.");
+    assert_eq!(json["messages"][1]["role"], "assistant");
+
+    let html = transcript.render(crate::transcript::Format::Html);
+    assert!(html.contains("<title>Cats &amp; Dogs</title>"));
+    assert!(html.contains("<h2>Summary</h2>"));
+    assert!(!html.contains("<script>"));
+
+    // only links to web pages are kept, images become links
+    assert!(html.contains(r#"<a href="https://example.com/cats">Cat facts</a>"#));
+    assert!(!html.contains("javascript:"));
+    assert!(html.contains("more"));
+    assert!(html.contains(r#"<a href="https://example.com/cat.png">a cat</a>"#));
+    assert!(!html.contains("<img"));
+
+    assert_eq!(
+        crate::transcript::Format::from_name("MD"),
+        Some(crate::transcript::Format::Markdown)
+    );
+    assert_eq!(crate::transcript::Format::from_name("pdf"), None);
+}
+
+#[wasm_bindgen_test]
+fn content_title() {
+    use crate::content::{title, ContentType};
+
+    let html = "<html><head><title>\n  Cats &amp; Dogs </title></head></html>";
+    assert_eq!(
+        title(html, ContentType::Html).as_deref(),
+        Some("Cats & Dogs")
+    );
+    assert_eq!(
+        title("Intro\n# Cats\n## Dogs", ContentType::Markdown).as_deref(),
+        Some("Cats")
+    );
+    assert_eq!(title("Cats", ContentType::PlainText), None);
+    assert_eq!(
+        crate::util::iso_datetime(1_741_944_413_000),
+        "2025-03-14T09:26:53Z"
+    );
+}
+
+#[wasm_bindgen_test]
+fn edit_session_turns() {
+    let store = &crate::session::STORE;
+    store.create_session("turns", vec![Message::system("Follow up")]);
+    store.append_messages(
+        "turns",
+        vec![
+            Message::user("one"),
+            Message::assistant("1"),
+            Message::user("two"),
+            Message::assistant("2"),
+            Message::user("three"),
+            Message::assistant("3"),
+        ],
+    );
+
+    // messages get stable ids in order
+    let messages = store.messages("turns").unwrap();
+    let ids = messages.iter().map(|m| m.id).collect::<Vec<u64>>();
+    assert_eq!(ids, vec![1, 2, 3, 4, 5, 6]);
+
+    // deleting an answer deletes its question as well
+    assert!(store.delete_turn("turns", 4));
+    let texts = |store: &crate::session::SessionStore| {
+        store
+            .messages("turns")
+            .unwrap()
+            .into_iter()
+            .map(|m| m.text)
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(texts(store), vec!["one", "1", "three", "3"]);
+    assert!(!store.delete_turn("turns", 4));
+
+    // asking again from a question removes it and everything after it
+    let removed = store.truncate_from("turns", 1).unwrap();
+    assert_eq!(removed.len(), 4);
+    assert!(texts(store).is_empty());
+
+    // removed messages can be put back with their ids, new messages get new ids
+    store.append_messages("turns", removed);
+    store.append_messages("turns", vec![Message::user("four")]);
+    let messages = store.messages("turns").unwrap();
+    assert_eq!(messages[0].id, 1);
+    assert_eq!(messages[4].id, 7);
+
+    // ids survive saving and restoring
+    let json = store.to_json_within(usize::MAX).to_string();
+    store.remove_session("turns");
+    store.restore(&json).unwrap();
+    assert_eq!(store.messages("turns").unwrap(), messages);
+    store.append_messages("turns", vec![Message::assistant("4")]);
+    assert_eq!(store.messages("turns").unwrap()[5].id, 8);
+}
+
+#[wasm_bindgen_test]
+fn fork_session() {
+    let store = &crate::session::STORE;
+    store.create_session("source", vec![Message::system("Follow up")]);
+    store.set_document("source", &"word ".repeat(1_000));
+    store.append_messages(
+        "source",
+        vec![
+            Message::user("one"),
+            Message::assistant("1"),
+            Message::user("two"),
+            Message::assistant("2"),
+        ],
+    );
+
+    // the chat is copied up to and including the given message
+    let bytes = store.stats().bytes;
+    assert!(store.fork("source", "fork", Some(2)));
+    let texts = |id: &str| {
+        store
+            .messages(id)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.text)
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(texts("fork"), vec!["one", "1"]);
+
+    // the document is shared, not copied
+    assert!(store.stats().bytes - bytes < 1_000);
+
+    // the sessions continue independently, with ids that don't clash
+    store.append_messages("fork", vec![Message::user("three")]);
+    assert_eq!(texts("source"), vec!["one", "1", "two", "2"]);
+    assert_eq!(texts("fork"), vec!["one", "1", "three"]);
+    assert_eq!(store.messages("fork").unwrap()[2].id, 5);
+
+    // without a message the whole chat is copied
+    assert!(store.fork("source", "copy", None));
+    assert_eq!(texts("copy"), texts("source"));
+
+    assert!(!store.fork("source", "missing", Some(42)));
+    assert!(!store.fork("unknown", "missing", None));
+    assert!(store.messages("missing").is_none());
+}
+
+#[wasm_bindgen_test]
+fn shared_documents() {
+    let store = &crate::session::STORE;
+    let text = "Shared article about otters. ".repeat(100);
+
+    // sessions about the same text share its document
+    store.create_session("tab-1", vec![Message::system("Follow up")]);
+    store.set_document("tab-1", &text);
+    let stats = store.stats();
+    store.create_session("tab-2", vec![Message::system("Follow up")]);
+    store.set_document("tab-2", &text);
+    assert_eq!(store.stats().documents, stats.documents);
+    assert!(store.stats().bytes - stats.bytes < 100);
+
+    // and its embeddings
+    let chunks = store.unembedded_chunks("tab-1").unwrap();
+    let vectors = chunks.iter().map(|_| vec![1.0, 0.0]).collect();
+    store.set_embeddings(
+        "tab-1",
+        crate::embedding::VectorIndex::new(vectors).unwrap(),
+    );
+    assert!(store.has_embeddings("tab-2"));
+
+    // the document is saved once and still shared when restored,
+    // its embeddings are left out and computed again when needed
+    let json = store.to_json_within(usize::MAX).to_string();
+    assert_eq!(json.matches("about otters").count(), 100);
+    assert!(!json.contains("embeddings"));
+    store.remove_session("tab-1");
+    store.remove_session("tab-2");
+    store.restore(&json).unwrap();
+    assert_eq!(store.stats().documents, stats.documents);
+    assert!(!store.has_embeddings("tab-1"));
+
+    // a session about another text gets its own document
+    store.set_document("tab-2", "Another article.");
+    assert_eq!(store.stats().documents, stats.documents + 1);
+
+    // sessions that don't fit the maximum size are left out, least recently used first
+    let json = store.to_json_within(1000).to_string();
+    assert!(json.contains("Another article."));
+    assert!(!json.contains("about otters"));
+}
+
+#[wasm_bindgen_test]
+fn summary_cache() {
+    let cache = &crate::cache::CACHE;
+    let summary = crate::cache::CachedSummary {
+        language: "english".to_string(),
+        summary: r#"{"summary": "Otters hold hands."}"#.to_string(),
+    };
+
+    // summaries are keyed by text, model and options
+    let key = crate::cache::key("Otters hold hands.", "gpt-4o", "general");
+    assert_ne!(
+        key,
+        crate::cache::key("Otters hold hands.", "gpt-4o-mini", "general")
+    );
+    assert_ne!(
+        key,
+        crate::cache::key("Otters hold hands.", "gpt-4o", "legal")
+    );
+    assert_ne!(key, crate::cache::key("Otters sleep.", "gpt-4o", "general"));
+
+    let stats = cache.stats();
+    assert!(cache.get(&key).is_none());
+    cache.put(&key, summary.clone());
+    assert_eq!(cache.get(&key), Some(summary.clone()));
+    assert_eq!(cache.stats().hits, stats.hits + 1);
+    assert_eq!(cache.stats().misses, stats.misses + 1);
+
+    // cached summaries can be saved and restored
+    let json = cache.to_json().to_string();
+    assert_eq!(cache.restore(&json).unwrap(), 0);
+    assert_eq!(cache.get(&key), Some(summary));
+    assert!(cache
+        .restore(r#"{"version": 1, "summaries": [{"key": "x"}]}"#)
+        .is_err());
+    assert!(cache.restore("{}").is_err());
+}
+
+#[wasm_bindgen_test]
+fn paragraph_diff() {
+    let old = "Live: storm hits the coast\n\
+        Winds reached 120 km/h on Monday morning.\n\
+        Schools are closed in the region.\n\
+        Ferries are cancelled until further notice.";
+    let new = "Live: storm hits the coast\n\
+        Winds reached 150 km/h on Monday morning.\n\n\
+        Schools are closed in the region.\n\
+        Power is out for 20,000 homes.";
+
+    let changes = crate::diff::diff(old, new);
+    let kinds = changes
+        .iter()
+        .map(|c| c.kind)
+        .collect::<Vec<crate::diff::ChangeKind>>();
+    assert_eq!(
+        kinds,
+        vec![
+            crate::diff::ChangeKind::Modified,
+            crate::diff::ChangeKind::Added,
+            crate::diff::ChangeKind::Removed
+        ]
+    );
+    assert_eq!(changes[0].text, "Winds reached 150 km/h on Monday morning.");
+    assert_eq!(
+        changes[0].previous.as_deref(),
+        Some("Winds reached 120 km/h on Monday morning.")
+    );
+    assert_eq!(changes[1].text, "Power is out for 20,000 homes.");
+
+    // unchanged text, blank lines and indentation aside
+    assert!(crate::diff::diff(old, &format!("\n{}\n", old.replace('\n', "\n\n  "))).is_empty());
+    assert_eq!(
+        crate::diff::diff("", "New page.")[0].kind,
+        crate::diff::ChangeKind::Added
+    );
+
+    // pages that changed throughout are replaced as a whole above a size
+    let old = (0..1500)
+        .map(|i| format!("Old paragraph {}", i))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let new = old.replace("Old", "New");
+    let changes = crate::diff::diff(&old, &new);
+    assert_eq!(changes.len(), 3000);
+    assert_eq!(changes[0].text, "New paragraph 0");
+    assert_eq!(changes[0].kind, crate::diff::ChangeKind::Added);
+    assert_eq!(changes[2999].kind, crate::diff::ChangeKind::Removed);
+}
+
+#[wasm_bindgen_test]
+fn page_snapshots() {
+    let cache = &crate::cache::CACHE;
+    assert!(cache.snapshot("https://example.com/live").is_none());
+
+    // snapshots are kept per page, regardless of the fragment
+    cache.set_snapshot("https://example.com/live#update-3", "First version.");
+    let snapshot = cache.snapshot("https://example.com/live").unwrap();
+    assert_eq!(snapshot.text, "First version.");
+    assert!(snapshot.taken_at > 0);
+
+    let json = cache.to_json().to_string();
+    cache.set_snapshot("https://example.com/live", "Second version.");
+    cache.restore(&json).unwrap();
+    assert_eq!(
+        cache.snapshot("https://example.com/live").unwrap().text,
+        "Second version."
+    );
+}
+
+#[wasm_bindgen_test]
+fn document_excerpt() {
+    let paragraph = "Reporters from several outlets covered the storm and its aftermath.";
+    let text = (0..200)
+        .map(|i| format!("{} {}", i, paragraph))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    // the excerpt is the beginning of the text in whole paragraphs
+    let excerpt = crate::retrieval::excerpt(&text, 1_000);
+    assert!(crate::tokens::estimate(&excerpt) <= 1_000);
+    assert!(crate::tokens::estimate(&excerpt) > 800);
+    assert!(excerpt.starts_with("0 Reporters"));
+    assert!(excerpt.ends_with("aftermath."));
+    assert_eq!(
+        crate::retrieval::excerpt("Short text.", 1_000),
+        "Short text."
+    );
+
+    let store = &crate::session::STORE;
+    store.create_session("outlet", vec![Message::system("Follow up")]);
+    assert!(store.document_text("outlet").is_none());
+    store.set_document("outlet", &text);
+    assert_eq!(store.document_text("outlet").unwrap(), text);
+}
+
+#[wasm_bindgen_test]
+async fn compare_documents() {
+    let first = r#"
+        <html><head><title>Storm hits the coast</title></head><body><article>
+            <p>A storm hit the coast on Monday with winds of up to 120 km/h. The weather service had warned of the storm two days earlier.</p>
+            <p>Officials said three people were injured. Schools in the region will stay closed until Wednesday.</p>
+        </article></body></html>
+    "#;
+    let second = r#"
+        <html><head><title>Coastal storm leaves thousands without power</title></head><body><article>
+            <p>Monday's storm brought winds of up to 150 km/h to the coast, according to local residents.</p>
+            <p>Officials said three people were injured. About 20,000 homes were without power on Monday evening.</p>
+        </article></body></html>
+    "#;
+
+    let result = crate::compare_documents(
+        vec![first.to_string(), second.to_string()],
+        TEST_MODEL,
+        TEST_API_KEY,
+    )
+    .await;
+    assert!(result.is_ok(), "Expected Ok, got {:?}", result);
+
+    let value: serde_json::Value = serde_json::from_str(&result.unwrap()).unwrap();
+    assert!(!value["common_points"].as_array().unwrap().is_empty());
+    assert!(value["disagreements"].is_array());
+    assert!(value["unique_claims"].is_array());
+    assert!(value["most_detailed"]["source"].is_u64());
+    assert_eq!(
+        value["sources"][1]["title"],
+        "Coastal storm leaves thousands without power"
+    );
+
+    // there is nothing to compare a single document with
+    let result = crate::compare_documents(vec![first.to_string()], TEST_MODEL, TEST_API_KEY).await;
+    assert!(result.is_err());
+}
+
+#[wasm_bindgen_test]
+async fn digest_sessions() {
+    let store = &crate::session::STORE;
+    store.create_session("tab-heat", vec![Message::system("Follow up")]);
+    store.set_document(
+        "tab-heat",
+        "Heat pumps move heat instead of generating it. In mild climates they deliver \
+        three to four units of heat for every unit of electricity they use.",
+    );
+    store.create_session("tab-grid", vec![Message::system("Follow up")]);
+    store.set_document(
+        "tab-grid",
+        "Utilities expect electricity demand to rise as homes replace gas boilers with \
+        heat pumps. Grid operators warn that cold snaps could strain local networks.",
+    );
+
+    let result = crate::digest(
+        "digest",
+        vec!["tab-heat".to_string(), "tab-grid".to_string()],
+        TEST_MODEL,
+        TEST_API_KEY,
+    )
+    .await;
+    assert!(result.is_ok(), "Expected Ok, got {:?}", result);
+
+    let value: serde_json::Value = serde_json::from_str(&result.unwrap()).unwrap();
+    assert!(!value["themes"].as_array().unwrap().is_empty());
+    assert_eq!(value["sources"][1]["session_id"], "tab-grid");
+
+    // the digest is a session about all sources
+    let context = store
+        .context_window_within("digest", None, usize::MAX)
+        .unwrap();
+    assert!(context[0].text.contains("[Source 2]"));
+    assert!(context[0].text.contains("cold snaps"));
+    assert!(store
+        .transcript("digest")
+        .and_then(|transcript| transcript.summary)
+        .is_some());
+
+    let result = crate::digest(
+        "digest",
+        vec!["tab-heat".to_string()],
+        TEST_MODEL,
+        TEST_API_KEY,
+    )
+    .await;
+    assert!(result.is_err());
+}
+
+#[wasm_bindgen_test]
+fn provider_credentials() {
+    // a single key is used for all providers
+    let credentials = crate::credentials::Credentials::parse(" sk-single ").unwrap();
+    assert_eq!(credentials.get("OpenAI").unwrap().api_key, "sk-single");
+    assert_eq!(credentials.get("Gemini").unwrap().api_key, "sk-single");
+
+    // keys by provider, with defaults for the other providers
+    let credentials = crate::credentials::Credentials::parse(
+        r#"{
+            "OpenAI": {"api_key": "sk-openai", "organization": "org-1", "headers": {"X-Team": "docs"}},
+            "gemini": "AIza-gemini",
+            "default": "sk-default"
+        }"#,
+    )
+    .unwrap();
+    let openai = credentials.get("openai").unwrap();
+    assert_eq!(openai.api_key, "sk-openai");
+    assert!(openai
+        .headers
+        .contains(&("OpenAI-Organization".to_string(), "org-1".to_string())));
+    assert!(openai
+        .headers
+        .contains(&("X-Team".to_string(), "docs".to_string())));
+    assert_eq!(credentials.get("Gemini").unwrap().api_key, "AIza-gemini");
+    assert!(credentials.get("Gemini").unwrap().headers.is_empty());
+    assert_eq!(credentials.get("Anthropic").unwrap().api_key, "sk-default");
+
+    // without defaults, other providers have no credentials
+    let credentials =
+        crate::credentials::Credentials::parse(r#"{"gemini": "AIza-gemini"}"#).unwrap();
+    assert!(credentials.get("openai").is_none());
+
+    assert!(crate::credentials::Credentials::parse("{not json").is_err());
+    assert!(crate::credentials::Credentials::parse(r#"{"openai": 42}"#).is_err());
+    let err = crate::credentials::Credentials::parse(r#"{"open-ai": "sk-openai"}"#).unwrap_err();
+    assert!(err.to_string().contains("open-ai"), "Got {}", err);
+    assert!(
+        crate::credentials::Credentials::parse(r#"{"openai": {"headers": {"X-Team": 1}}}"#)
+            .is_err()
+    );
+}
+
+#[wasm_bindgen_test]
+fn custom_endpoints() {
+    let parsed = crate::endpoints::parse(
+        r#"{
+            "local-llama": {"url": "http://localhost:8080/v1", "model": "llama-3.1-8b"},
+            "llama3.2": {"url": "http://gpu-box:11434/", "adapter": "Ollama"},
+            "gateway-gpt": {
+                "url": "https://gateway.example.com/openai/",
+                "model": "gpt-4o",
+                "api_key": "gw-key",
+                "headers": {"api-key": "gw-key"}
+            }
+        }"#,
+    )
+    .unwrap();
+
+    // the adapters append their paths to the base url
+    let local = &parsed["local-llama"];
+    assert_eq!(local.url, "http://localhost:8080/v1/");
+    assert_eq!(local.adapter, "openai");
+    assert_eq!(local.model, "llama-3.1-8b");
+    assert_eq!(local.api_key, None);
+
+    // the model defaults to the alias
+    let ollama = &parsed["llama3.2"];
+    assert_eq!(ollama.adapter, "ollama");
+    assert_eq!(ollama.model, "llama3.2");
+
+    let gateway = &parsed["gateway-gpt"];
+    assert_eq!(gateway.api_key.as_deref(), Some("gw-key"));
+    assert_eq!(
+        gateway.headers,
+        vec![("api-key".to_string(), "gw-key".to_string())]
+    );
+
+    crate::endpoints::ENDPOINTS.configure(parsed);
+    assert_eq!(
+        crate::endpoints::ENDPOINTS
+            .get("local-llama")
+            .unwrap()
+            .model,
+        "llama-3.1-8b"
+    );
+    assert!(crate::endpoints::ENDPOINTS.get("gpt-4o").is_none());
+
+    // endpoints without headers of their own send those of their adapter
+    let credentials = crate::credentials::Credentials::parse(
+        r#"{"openai": {"api_key": "sk-openai", "headers": {"X-Team": "docs"}}}"#,
+    )
+    .unwrap();
+    let team = vec![("X-Team".to_string(), "docs".to_string())];
+    assert_eq!(crate::request_headers("local-llama", &credentials), team);
+    assert_eq!(crate::request_headers("gpt-4o", &credentials), team);
+    assert_eq!(
+        crate::request_headers("gateway-gpt", &credentials),
+        vec![("api-key".to_string(), "gw-key".to_string())]
+    );
+    assert!(crate::request_headers("llama3.2", &credentials).is_empty());
+
+    // configuring replaces all endpoints, an empty configuration has none
+    crate::endpoints::ENDPOINTS.configure(crate::endpoints::parse(" ").unwrap());
+    assert!(crate::endpoints::ENDPOINTS.get("local-llama").is_none());
+
+    assert!(crate::endpoints::parse(r#"{"a": {"adapter": "openai"}}"#).is_err());
+    assert!(crate::endpoints::parse(r#"{"a": {"url": "not a url"}}"#).is_err());
+    assert!(crate::endpoints::parse(r#"{"a": {"url": "ftp://host/"}}"#).is_err());
+    assert!(
+        crate::endpoints::parse(r#"{"a": {"url": "http://host/", "adapter": "nope"}}"#).is_err()
+    );
+    assert!(crate::endpoints::parse(r#"["http://host/"]"#).is_err());
+
+    // adapters are named like their adapter kind, case is ignored
+    assert_eq!(
+        crate::endpoints::adapter_kind("DeepSeek"),
+        Some(genai::adapter::AdapterKind::DeepSeek)
+    );
+    assert!(crate::endpoints::adapter_kind("nope").is_none());
+    assert!(crate::endpoints::adapter_names().contains(&"xai".to_string()));
+}
+
+#[wasm_bindgen_test]
+fn model_catalog() {
+    // listings are sorted, without duplicates and the models that can't chat
+    let names = [
+        "gpt-4o-mini",
+        "text-embedding-3-small",
+        "whisper-1",
+        "gpt-4o",
+        "gpt-4o",
+    ]
+    .map(str::to_string)
+    .to_vec();
+    assert_eq!(
+        crate::models::chat_models(names),
+        vec!["gpt-4o", "gpt-4o-mini"]
+    );
+
+    assert!(crate::models::known("deepseek").contains(&"deepseek-chat".to_string()));
+    assert!(crate::models::known("nope").is_empty());
+
+    let gpt = crate::models::ModelInfo::new("openai", "gpt-4o");
+    assert!(gpt.json_schema && gpt.streaming && gpt.vision);
+    assert_eq!(gpt.context_size, 128_000);
+
+    let llama = crate::models::ModelInfo::new("groq", "llama-3.3-70b-versatile");
+    assert!(!llama.json_schema);
+    assert!(!llama.vision);
+    assert!(crate::models::ModelInfo::new("groq", "llama-3.2-11b-vision-preview").vision);
+    assert!(!crate::models::ModelInfo::new("openai", "o1-mini").vision);
+    assert!(!crate::models::ModelInfo::new("deepseek", "deepseek-chat").vision);
+}
+
+#[wasm_bindgen_test]
+fn access_diagnostics() {
+    assert!(crate::diagnostics::is_confirmation("Access confirmed"));
+    assert!(crate::diagnostics::is_confirmation(
+        " \"Access confirmed.\"\n"
+    ));
+    assert!(crate::diagnostics::is_confirmation("ACCESS CONFIRMED!"));
+    assert!(!crate::diagnostics::is_confirmation("Yes, it is working."));
+
+    use crate::diagnostics::Cause;
+    assert_eq!(
+        Cause::classify(
+            r#"WebModelCall { webc_error: ResponseFailedStatus { status: 401, body: "..." } }"#
+        ),
+        Some(Cause::AuthRejected)
+    );
+    assert_eq!(
+        Cause::classify(r#"status: 400, body: "API key not valid. Please pass a valid API key.""#),
+        Some(Cause::AuthRejected)
+    );
+    assert_eq!(
+        Cause::classify(r#"status: 429, body: "You exceeded your current quota""#),
+        Some(Cause::QuotaExceeded)
+    );
+    assert_eq!(
+        Cause::classify(r#"status: 404, body: "The model `gpt-5o` does not exist""#),
+        Some(Cause::UnknownModel)
+    );
+    assert_eq!(
+        Cause::classify(
+            "reqwest::Error { kind: Request, source: \"error sending request for url\" }"
+        ),
+        Some(Cause::NetworkUnreachable)
+    );
+    // a connection that timed out is a timeout, not an unreachable endpoint
+    assert_eq!(
+        Cause::classify("error sending request: connection timed out"),
+        Some(Cause::Timeout)
+    );
+    assert_eq!(
+        Cause::classify("StreamParse: expected value at line 1 column 1"),
+        Some(Cause::MalformedResponse)
+    );
+    assert_eq!(Cause::classify("something else entirely"), None);
+}
+
+#[wasm_bindgen_test]
+fn error_codes() {
+    let error = |detail: &str| crate::error::SummyError::from_llm(detail.to_string());
+
+    let auth = error(
+        r#"WebModelCall { webc_error: ResponseFailedStatus { status: 401, body: "invalid_api_key" } }"#,
+    );
+    assert_eq!(auth.code(), "auth_rejected");
+    assert!(!auth.retryable());
+    assert!(auth.detail().contains("invalid_api_key"));
+
+    let rate_limit = error(r#"ResponseFailedStatus { status: 429, body: "Rate limit reached" }"#);
+    assert_eq!(rate_limit.code(), "rate_limited");
+    assert!(rate_limit.retryable());
+
+    assert_eq!(error("operation timed out").code(), "timeout");
+    assert_eq!(
+        error("AbortError: The user aborted a request.").code(),
+        "cancelled"
+    );
+    assert_eq!(
+        error(r#"status: 400, body: "code": "context_length_exceeded""#).code(),
+        "context_too_long"
+    );
+    assert_eq!(
+        error("expected value at line 1").code(),
+        "invalid_model_output"
+    );
+    assert_eq!(error("something else entirely").code(), "llm_failed");
+
+    // every cause has its own code
+    let quota = error(r#"status: 429, body: "You exceeded your current quota""#);
+    assert_eq!(quota.code(), "rate_limited");
+    let unknown = error(r#"status: 404, body: "model_not_found""#);
+    assert_eq!(unknown.code(), "unknown_model");
+    assert!(!unknown.retryable());
+    let network = error("error sending request for url");
+    assert_eq!(network.code(), "network_unreachable");
+    assert!(network.retryable());
+
+    // messages for users don't contain the details
+    let expired = crate::error::SummyError::SessionNotFound {
+        session_id: "42".to_string(),
+        expired: true,
+    };
+    assert_eq!(expired.code(), "session_not_found");
+    assert!(!expired.message().contains("42"));
+    assert_eq!(expired.detail(), "Session 42 expired");
+    assert_eq!(expired.to_string(), "session_not_found: Session 42 expired");
+
+    // invalid input is described by our own details
+    let input = crate::error::SummyError::InvalidInput("Unsupported format: pdf".to_string());
+    assert_eq!(input.message(), "Unsupported format: pdf");
+    assert!(!input.retryable());
+
+    // the exports fail with these errors
+    let missing = crate::session_info("no-such-session").unwrap_err();
+    assert_eq!(missing.code(), "session_not_found");
+    assert_eq!(
+        crate::export_transcript("no-such-session", "docx")
+            .unwrap_err()
+            .code(),
+        "invalid_input"
+    );
+}
+
 // Test helpers
 mod helpers {
     use crate::session::Message;
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Document, Object, Stream};
     use unicode_segmentation::UnicodeSegmentation;
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = Reflect, js_name = get)]
+        pub fn reflect_get(target: &JsValue, key: &JsValue) -> JsValue;
+    }
+
+    // Helper function to read a string property of a JavaScript error
+    pub fn property(error: &JsValue, key: &str) -> String {
+        reflect_get(error, &JsValue::from_str(key))
+            .as_string()
+            .unwrap_or_default()
+    }
 
     // Helper function to create new session with given id and html content
     pub fn create_session(id: &str, html: &str) {
@@ -466,11 +2129,9 @@ mod helpers {
 
         crate::session::STORE.create_session(
             session_id,
-            vec![
-                Message::user(text.as_str()),
-                Message::system(crate::FOLLOW_UP_SYSTEM_PROMPT),
-            ],
+            vec![Message::system(crate::FOLLOW_UP_SYSTEM_PROMPT)],
         );
+        crate::session::STORE.set_document(session_id, &text);
     }
 
     // Helper function to assert summary response properties
@@ -544,4 +2205,62 @@ mod helpers {
             emoji_outline
         );
     }
+
+    // Helper function to create a PDF with the given pages,
+    // each page is a list of (text, font size, x, y)
+    pub fn pdf(pages: &[&[(&str, f32, f32, f32)]]) -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        let resources_id = doc.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+
+        let mut kids = Vec::new();
+        for lines in pages {
+            let mut operations = Vec::new();
+            for (text, size, x, y) in lines.iter() {
+                operations.push(Operation::new("BT", vec![]));
+                operations.push(Operation::new("Tf", vec!["F1".into(), (*size).into()]));
+                operations.push(Operation::new("Td", vec![(*x).into(), (*y).into()]));
+                operations.push(Operation::new("Tj", vec![Object::string_literal(*text)]));
+                operations.push(Operation::new("ET", vec![]));
+            }
+
+            let content = Content { operations };
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+            let page_id = doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+            });
+            kids.push(page_id.into());
+        }
+
+        let count = kids.len() as i64;
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => count,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            }),
+        );
+
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        bytes
+    }
 }
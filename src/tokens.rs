@@ -0,0 +1,64 @@
+// Context size we assume for models we don't know. Small on purpose,
+// local models served by Ollama often run with a small context.
+const DEFAULT_CONTEXT_SIZE: usize = 8_192;
+
+// Number of tokens we keep free for the model's reply
+const RESPONSE_RESERVE: usize = 2_048;
+
+// Context sizes by model name prefix, the first matching prefix wins,
+// so more specific prefixes need to come first
+const CONTEXT_SIZES: [(&str, usize); 20] = [
+    ("gemini-1.0", 32_768),
+    ("gemini", 1_048_576),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("claude", 200_000),
+    ("deepseek", 64_000),
+    ("grok", 131_072),
+    ("llama-3.1", 128_000),
+    ("llama-3.2", 128_000),
+    ("llama-3.3", 128_000),
+    ("llama3", 8_192),
+    ("mixtral", 32_768),
+    ("mistral", 32_768),
+    ("gemma", 8_192),
+];
+
+// Estimate the number of tokens of the given text. Tokenizers differ
+// between models, so this is a rough, slightly pessimistic estimate:
+// about 4 characters per token for latin text, one token per character
+// for other scripts like CJK.
+pub fn estimate(text: &str) -> usize {
+    let (ascii, other) = text.chars().fold((0usize, 0usize), |(ascii, other), c| {
+        if c.is_ascii() {
+            (ascii + 1, other)
+        } else {
+            (ascii, other + 1)
+        }
+    });
+
+    ascii.div_ceil(4) + other
+}
+
+// Context size of the given model in tokens
+pub fn context_size(model: &str) -> usize {
+    // strip the adapter namespace, e.g. "ollama::llama3"
+    let name = model.rsplit("::").next().unwrap_or(model).to_lowercase();
+
+    CONTEXT_SIZES
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, size)| *size)
+        .unwrap_or(DEFAULT_CONTEXT_SIZE)
+}
+
+// Number of tokens we can send to the given model, leaving room for the reply
+pub fn budget(model: &str) -> usize {
+    context_size(model).saturating_sub(RESPONSE_RESERVE)
+}
@@ -0,0 +1,222 @@
+use crate::session::{Message, MessageSource, Summary};
+use pulldown_cmark::{Event, LinkType, Parser, Tag, TagEnd};
+
+// Link destinations kept in HTML transcripts, others like "javascript:" are dropped
+const SAFE_URL_SCHEMES: [&str; 3] = ["http://", "https://", "mailto:"];
+
+// Formats a transcript can be exported in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Markdown,
+    Json,
+    Html,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "markdown" | "md" => Some(Format::Markdown),
+            "json" => Some(Format::Json),
+            "html" => Some(Format::Html),
+            _ => None,
+        }
+    }
+}
+
+// The summary and conversation of a session, for users to keep in their notes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transcript {
+    pub title: Option<String>,
+    pub model: Option<String>,
+
+    // Milliseconds since the Unix epoch
+    pub created_at: u64,
+    pub updated_at: u64,
+
+    pub summary: Option<Summary>,
+
+    // Recap of older messages that are no longer available
+    pub recap: Option<String>,
+
+    // Questions and answers, oldest first
+    pub messages: Vec<Message>,
+}
+
+impl Transcript {
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Markdown => self.to_markdown(),
+            Format::Json => self.to_json().to_string(),
+            Format::Html => self.to_html(),
+        }
+    }
+
+    fn title(&self) -> &str {
+        self.title.as_deref().unwrap_or("Summy conversation")
+    }
+
+    fn language(&self) -> Option<&str> {
+        self.summary
+            .as_ref()
+            .map(|summary| summary.language.as_str())
+            .filter(|language| !language.is_empty())
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n\n", self.title());
+
+        let mut details = Vec::new();
+        if let Some(model) = &self.model {
+            details.push(format!("Model: {}", model));
+        }
+        if let Some(language) = self.language() {
+            details.push(format!("Language: {}", language));
+        }
+        details.push(format!(
+            "Created: {}",
+            crate::util::iso_datetime(self.created_at)
+        ));
+        details.push(format!(
+            "Updated: {}",
+            crate::util::iso_datetime(self.updated_at)
+        ));
+        out.push_str(&format!("_{}_\n\n", details.join(" · ")));
+
+        if let Some(summary) = &self.summary {
+            out.push_str(&format!("## Summary\n\n{}\n\n", summary.text));
+
+            if !summary.questions.is_empty() {
+                out.push_str("### Suggested questions\n\n");
+                for qa in &summary.questions {
+                    out.push_str(&format!("**{}**\n\n{}\n\n", qa.question, qa.answer));
+                }
+            }
+        }
+
+        if self.recap.is_some() || !self.messages.is_empty() {
+            out.push_str("## Conversation\n\n");
+        }
+        if let Some(recap) = &self.recap {
+            out.push_str(&format!("> Earlier conversation, condensed: {}\n\n", recap));
+        }
+        for message in &self.messages {
+            out.push_str(&format!(
+                "**{}:** {}\n\n",
+                speaker(message.source),
+                message.text
+            ));
+        }
+
+        out.trim_end().to_string() + "\n"
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let summary = self.summary.as_ref().map(|summary| {
+            serde_json::json!({
+                "text": summary.text,
+                "category": summary.category,
+                "questions": summary.questions.iter().map(|qa| serde_json::json!({
+                    "question": qa.question,
+                    "answer": qa.answer,
+                })).collect::<Vec<serde_json::Value>>(),
+            })
+        });
+
+        let messages = self
+            .messages
+            .iter()
+            .map(|message| {
+                serde_json::json!({
+                    "id": message.id,
+                    "role": message.source.name(),
+                    "text": message.text,
+                })
+            })
+            .collect::<Vec<serde_json::Value>>();
+
+        serde_json::json!({
+            "title": self.title,
+            "model": self.model,
+            "language": self.language(),
+            "created_at": crate::util::iso_datetime(self.created_at),
+            "updated_at": crate::util::iso_datetime(self.updated_at),
+            "summary": summary,
+            "recap": self.recap,
+            "messages": messages,
+        })
+    }
+
+    // Render the markdown transcript as a standalone HTML page. Raw HTML in
+    // the answers is escaped, the model may have been tricked by the page
+    // into writing markup we don't want to run when the file is opened.
+    // For the same reason only links to web pages and mail addresses are kept,
+    // and images become links, so opening the file doesn't load anything.
+    fn to_html(&self) -> String {
+        let markdown = self.to_markdown();
+
+        // whether the start of each open link or image was kept, for its end
+        let mut kept = Vec::new();
+        let events = Parser::new(&markdown).filter_map(|event| match event {
+            Event::Html(html) | Event::InlineHtml(html) => Some(Event::Text(html)),
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            })
+            | Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                // email autolinks get their "mailto:" when rendered
+                let safe = link_type == LinkType::Email || is_safe_url(&dest_url);
+                kept.push(safe);
+                safe.then_some(Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }))
+            }
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => kept
+                .pop()
+                .unwrap_or_default()
+                .then_some(Event::End(TagEnd::Link)),
+            event => Some(event),
+        });
+
+        let mut body = String::new();
+        pulldown_cmark::html::push_html(&mut body, events);
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+            escape_html(self.title()),
+            body
+        )
+    }
+}
+
+fn speaker(source: MessageSource) -> &'static str {
+    match source {
+        MessageSource::User => "You",
+        MessageSource::Assistant => "Summy",
+        MessageSource::System => "System",
+    }
+}
+
+fn is_safe_url(url: &str) -> bool {
+    let url = url.trim().to_lowercase();
+    SAFE_URL_SCHEMES
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
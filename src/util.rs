@@ -9,3 +9,73 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
+
+// Milliseconds since the Unix epoch. `std::time` isn't available
+// in the browser, so we ask JavaScript for the time there.
+#[cfg(target_arch = "wasm32")]
+pub fn now() -> u64 {
+    js_now() as u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Date, js_name = now)]
+    fn js_now() -> f64;
+}
+
+// FNV-1a hash, stable across platforms and runs unlike the std hasher
+#[cfg(test)]
+pub fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Hash that identifies a text by its content, as 64 hex digits. Texts with the same
+// hash share documents and cached summaries, so this needs to be collision resistant.
+pub fn content_hash(text: &str) -> String {
+    use sha2::Digest;
+
+    sha2::Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Format milliseconds since the Unix epoch as an ISO 8601 date in UTC,
+// e.g. "2025-03-14T09:26:53Z"
+pub fn iso_datetime(millis: u64) -> String {
+    let secs = millis / 1000;
+    let (days, time) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // civil date from days since the epoch, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60
+    )
+}